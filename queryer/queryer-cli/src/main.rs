@@ -0,0 +1,60 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use queryer::{Context, LoadOptions};
+
+mod output;
+mod repl;
+
+use output::Mode;
+use repl::Session;
+
+/// Query csv/json sources with SQL
+#[derive(Debug, Parser)]
+#[command(name = "queryer", version)]
+struct Args {
+    /// Run a single statement and exit
+    #[arg(short, long)]
+    command: Option<String>,
+
+    /// Output mode
+    #[arg(short, long, value_enum, default_value = "table")]
+    output: Mode,
+
+    /// Register a source as NAME=URI, can be repeated
+    #[arg(short, long = "source", value_parser = parse_source)]
+    sources: Vec<(String, String)>,
+
+    /// Show how long each statement takes
+    #[arg(short, long)]
+    timer: bool,
+
+    /// Convert string columns of registered sources that hold only dates or timestamps
+    #[arg(long)]
+    parse_dates: bool,
+}
+
+fn parse_source(s: &str) -> Result<(String, String)> {
+    let (name, uri) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expect NAME=URI, got {}", s))?;
+    Ok((name.to_string(), uri.to_string()))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let mut ctx = Context::new();
+    let options = LoadOptions::default().with_parse_dates(args.parse_dates);
+    for (name, uri) in args.sources {
+        ctx.register_source(name, uri, options.clone());
+    }
+
+    let mut session = Session::new(ctx, args.output, options);
+    session.timer = args.timer;
+
+    match args.command {
+        Some(sql) => session.execute(sql.trim().trim_end_matches(';')).await,
+        None => repl::run(session).await,
+    }
+}
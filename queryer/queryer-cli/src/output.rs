@@ -0,0 +1,116 @@
+use anyhow::{anyhow, Result};
+use queryer::{AnyValue, DataSet, Format};
+use std::str::FromStr;
+
+/// 查询结果的显示方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Mode {
+    Table,
+    Csv,
+    Json,
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Mode::Table),
+            "csv" => Ok(Mode::Csv),
+            "json" => Ok(Mode::Json),
+            v => Err(anyhow!("Unknown mode {}, expect csv, table or json", v)),
+        }
+    }
+}
+
+/// 按显示方式把 DataSet 渲染成字符串
+pub fn render(ds: &DataSet, mode: Mode) -> Result<String> {
+    match mode {
+        Mode::Table => Ok(render_table(ds)),
+        Mode::Csv => Ok(String::from_utf8(ds.serialize(Format::Csv)?)?),
+        Mode::Json => Ok(String::from_utf8(ds.serialize(Format::Json)?)?),
+    }
+}
+
+/// 画一个带边框的表格
+fn render_table(ds: &DataSet) -> String {
+    let columns = ds.get_columns();
+    let header: Vec<String> = columns.iter().map(|s| s.name().to_string()).collect();
+    let rows: Vec<Vec<String>> = (0..ds.height())
+        .map(|i| columns.iter().map(|s| cell(s.get(i))).collect())
+        .collect();
+
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (w, v) in widths.iter_mut().zip(row) {
+            *w = (*w).max(v.chars().count());
+        }
+    }
+
+    let border = widths
+        .iter()
+        .map(|w| "-".repeat(w + 2))
+        .collect::<Vec<_>>()
+        .join("+");
+    let border = format!("+{}+\n", border);
+    let line = |values: &[String]| {
+        let cells: Vec<String> = values
+            .iter()
+            .zip(&widths)
+            .map(|(v, w)| format!(" {}{} ", v, " ".repeat(w - v.chars().count())))
+            .collect();
+        format!("|{}|\n", cells.join("|"))
+    };
+
+    let mut out = border.clone();
+    out.push_str(&line(&header));
+    out.push_str(&border);
+    for row in &rows {
+        out.push_str(&line(row));
+    }
+    if !rows.is_empty() {
+        out.push_str(&border);
+    }
+    out.push_str(&format!("({} rows)\n", rows.len()));
+    out
+}
+
+fn cell(v: AnyValue) -> String {
+    match v {
+        AnyValue::Null => "null".to_string(),
+        AnyValue::Utf8(s) => s.to_string(),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn render_works() {
+        let mut file = tempfile::Builder::new().suffix(".csv").tempfile().unwrap();
+        file.write_all(b"name,value\nalpha,1\nb,\n").unwrap();
+        let sql = format!("SELECT name, value FROM 'file://{}'", file.path().display());
+        let ds = queryer::query(sql).await.unwrap();
+
+        assert_eq!(
+            render(&ds, Mode::Table).unwrap(),
+            "+-------+-------+\n\
+             | name  | value |\n\
+             +-------+-------+\n\
+             | alpha | 1     |\n\
+             | b     | null  |\n\
+             +-------+-------+\n\
+             (2 rows)\n"
+        );
+        assert_eq!(render(&ds, Mode::Csv).unwrap(), "name,value\nalpha,1\nb,\n");
+    }
+
+    #[test]
+    fn parse_mode_works() {
+        assert_eq!("csv".parse::<Mode>().unwrap(), Mode::Csv);
+        assert!("xml".parse::<Mode>().is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::output::{render, Mode};
+use anyhow::{anyhow, Result};
+use queryer::{CancelToken, Context, LoadOptions, Params, QueryOptions};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::path::PathBuf;
+use std::time::Instant;
+
+const HELP: &str = "\
+.help                   Show this message
+.tables                 List registered sources
+.schema [source]        Show columns of a source, or of every registered source
+.register <name> <uri>  Register a source under a name
+.mode csv|table|json    Set the output mode
+.timer on|off           Show how long each statement takes
+.quit                   Exit
+
+SQL statements end with `;` and may span multiple lines.";
+
+/// REPL 的状态
+pub struct Session {
+    pub ctx: Context,
+    pub mode: Mode,
+    pub timer: bool,
+    /// `.register` 注册数据源时用的加载选项，和命令行的 `-s` 一样
+    pub options: LoadOptions,
+}
+
+enum Flow {
+    Continue,
+    Exit,
+}
+
+impl Session {
+    pub fn new(ctx: Context, mode: Mode, options: LoadOptions) -> Self {
+        Self {
+            ctx,
+            mode,
+            timer: false,
+            options,
+        }
+    }
+
+    /// 执行一条 SQL 并打印结果，执行时按 Ctrl-C 取消查询
+    pub async fn execute(&self, sql: &str) -> Result<()> {
+        let start = Instant::now();
+        let token = CancelToken::new();
+        let options = QueryOptions::default().with_cancel_token(token.clone());
+        let query = self.ctx.query_with_options(sql, Params::new(), options);
+        tokio::pin!(query);
+        let ds = tokio::select! {
+            result = &mut query => result?,
+            _ = tokio::signal::ctrl_c() => {
+                token.cancel();
+                query.await?
+            }
+        };
+        print!("{}", render(&ds, self.mode)?);
+        if self.timer {
+            println!("Run Time: {:.3}s", start.elapsed().as_secs_f64());
+        }
+        Ok(())
+    }
+
+    async fn command(&mut self, line: &str) -> Result<Flow> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        match args.as_slice() {
+            [".help"] => println!("{}", HELP),
+            [".quit"] | [".exit"] => return Ok(Flow::Exit),
+            [".tables"] => {
+                let mut tables: Vec<_> = self.ctx.tables().collect();
+                tables.sort_by_key(|(name, _)| *name);
+                for (name, table) in tables {
+                    println!("{}\t{}", name, table.uri);
+                }
+            }
+            [".schema"] => {
+                let mut names: Vec<String> =
+                    self.ctx.tables().map(|(n, _)| n.to_string()).collect();
+                names.sort();
+                for name in names {
+                    println!("{}:", name);
+                    self.describe(&name).await?;
+                }
+            }
+            [".schema", source] => self.describe(source).await?,
+            [".register", name, uri] => {
+                self.ctx.register_source(*name, *uri, self.options.clone());
+            }
+            [".mode", mode] => self.mode = mode.parse()?,
+            [".timer", "on"] => self.timer = true,
+            [".timer", "off"] => self.timer = false,
+            _ => return Err(anyhow!("Unknown command {}, try .help", line)),
+        }
+        Ok(Flow::Continue)
+    }
+
+    async fn describe(&self, source: &str) -> Result<()> {
+        let ds = self.ctx.read_source(source).await?.describe()?;
+        print!("{}", render(&ds, self.mode)?);
+        Ok(())
+    }
+}
+
+/// 输入是否是完整的语句：最后一个字符是 `;`，并且不在引号或者 `--` 注释里
+fn statement_complete(input: &str) -> bool {
+    let mut quote = None;
+    let mut comment = false;
+    let mut last = None;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            _ if comment => comment = c != '\n',
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '-') if chars.peek() == Some(&'-') => comment = true,
+            _ => {}
+        }
+        if !comment && !c.is_whitespace() {
+            last = Some(c);
+        }
+    }
+    quote.is_none() && last == Some(';')
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".queryer_history"))
+}
+
+/// 交互式执行：以 `.` 开头的是命令，SQL 以 `;` 结尾，可以跨多行
+pub async fn run(mut session: Session) -> Result<()> {
+    let mut rl = DefaultEditor::new()?;
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = rl.load_history(path);
+    }
+
+    println!(
+        "queryer {}, enter .help for usage hints",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let mut buf = String::new();
+    loop {
+        let prompt = if buf.is_empty() {
+            "queryer> "
+        } else {
+            "    ...> "
+        };
+        let line = match rl.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C 放弃当前输入的语句
+            Err(ReadlineError::Interrupted) => {
+                buf.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        let trimmed = line.trim();
+        if buf.is_empty() && trimmed.starts_with('.') {
+            rl.add_history_entry(trimmed)?;
+            match session.command(trimmed).await {
+                Ok(Flow::Exit) => break,
+                Ok(Flow::Continue) => {}
+                Err(e) => eprintln!("Error: {}", e),
+            }
+            continue;
+        }
+
+        if trimmed.is_empty() && buf.is_empty() {
+            continue;
+        }
+        buf.push_str(&line);
+        buf.push('\n');
+
+        if statement_complete(&buf) {
+            let sql = std::mem::take(&mut buf);
+            let sql = sql.trim();
+            rl.add_history_entry(sql)?;
+            if let Err(e) = session.execute(sql.trim_end_matches(';')).await {
+                eprintln!("Error: {}", e);
+            }
+        }
+    }
+
+    if let Some(path) = &history {
+        let _ = rl.save_history(path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statement_complete_works() {
+        assert!(statement_complete("SELECT 1;\n"));
+        assert!(statement_complete("SELECT 'a;'\n;\n"));
+        assert!(statement_complete("SELECT 1; -- done\n"));
+        assert!(!statement_complete("SELECT 'a;\n"));
+        assert!(!statement_complete("SELECT \"a;\n"));
+        assert!(!statement_complete("SELECT 'it''s;\n"));
+        assert!(!statement_complete("SELECT 1 -- not yet;\n"));
+        assert!(!statement_complete("SELECT 1\n"));
+    }
+
+    #[tokio::test]
+    async fn register_uses_session_options() {
+        let options = LoadOptions::default().with_parse_dates(true);
+        let mut session = Session::new(Context::new(), Mode::Table, options.clone());
+        session
+            .command(".register t file:///tmp/t.csv")
+            .await
+            .unwrap();
+        assert_eq!(session.ctx.table("t").unwrap().options, options);
+    }
+}
@@ -1,4 +1,26 @@
+// pyo3 的宏展开会对 Option<&str> 参数触发这个 lint
+#![allow(clippy::needless_option_as_deref)]
+
 use pyo3::{exceptions, prelude::*};
+use queryer::QueryError;
+
+/// 把 QueryError 按种类转换成对应的 Python 异常
+fn to_py_err(e: QueryError) -> PyErr {
+    let msg = e.to_string();
+    match e {
+        QueryError::Parse(_) => exceptions::PySyntaxError::new_err(msg),
+        QueryError::Unsupported { .. } => exceptions::PyNotImplementedError::new_err(msg),
+        QueryError::Param(_) | QueryError::InvalidRegex { .. } => {
+            exceptions::PyValueError::new_err(msg)
+        }
+        QueryError::Fetch { .. } => exceptions::PyIOError::new_err(msg),
+        QueryError::NotAllowed(_) => exceptions::PyPermissionError::new_err(msg),
+        QueryError::UnknownColumn { .. } | QueryError::Load(_) => {
+            exceptions::PyValueError::new_err(msg)
+        }
+        _ => exceptions::PyRuntimeError::new_err(msg),
+    }
+}
 
 #[pyfunction]
 pub fn example_sql() -> PyResult<String> {
@@ -7,10 +29,10 @@ pub fn example_sql() -> PyResult<String> {
 
 #[pyfunction]
 pub fn query(sql: &str, output: Option<&str>) -> PyResult<String> {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    let data = rt.block_on(async { queryer::query(sql).await.unwrap() });
+    let rt = tokio::runtime::Runtime::new()?;
+    let data = rt.block_on(queryer::query(sql)).map_err(to_py_err)?;
     match output {
-        Some("csv") | None => Ok(data.to_csv().unwrap()),
+        Some("csv") | None => data.to_csv().map_err(to_py_err),
         Some(v) => Err(exceptions::PyTypeError::new_err(format!(
             "Output type {} not supported",
             v
@@ -23,4 +45,4 @@ fn queryer_py(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(query, m)?)?;
     m.add_function(wrap_pyfunction!(example_sql, m)?)?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,126 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use queryer::{Context, Limits, LoadOptions};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+mod pgwire;
+mod response;
+mod server;
+
+use server::{Config, State};
+
+/// Serve SQL queries over HTTP (POST /query with the SQL as the body),
+/// and optionally over the PostgreSQL wire protocol
+#[derive(Debug, Parser)]
+#[command(name = "queryer-server", version)]
+struct Args {
+    /// Address to listen on
+    #[arg(short, long, default_value = "127.0.0.1:8080")]
+    addr: SocketAddr,
+
+    /// Also accept PostgreSQL clients (psql, drivers) on this address. Without
+    /// --pg-password any client that can reach it may query, so keep it on a
+    /// loopback address such as 127.0.0.1:5432
+    #[arg(long)]
+    pg_addr: Option<SocketAddr>,
+
+    /// Require PostgreSQL clients to authenticate with this password using MD5.
+    /// There is no TLS, so queries and results travel unencrypted and a captured
+    /// handshake can be brute-forced offline; use a long random password
+    #[arg(long)]
+    pg_password: Option<String>,
+
+    /// Register a source as NAME=URI, can be repeated
+    #[arg(short, long = "source", value_parser = parse_source)]
+    sources: Vec<(String, String)>,
+
+    /// Abort queries running longer than this many seconds
+    #[arg(long, default_value_t = 30)]
+    timeout: u64,
+
+    /// Return at most this many rows
+    #[arg(long, default_value_t = 10_000)]
+    max_rows: usize,
+
+    /// Refuse queries (request bodies) larger than this many bytes
+    #[arg(long, default_value_t = 1 << 20)]
+    max_body_bytes: usize,
+
+    /// Refuse sources larger than this many bytes
+    #[arg(long)]
+    max_fetch_bytes: Option<u64>,
+
+    /// Refuse sources with more than this many rows
+    #[arg(long)]
+    max_rows_loaded: Option<usize>,
+
+    /// Allow COPY ... TO statements, which write files on this host
+    #[arg(long)]
+    allow_copy: bool,
+
+    /// Allow queries to read any file path or URL, not only the registered sources
+    #[arg(long)]
+    allow_any_source: bool,
+
+    /// Convert string columns of registered sources that hold only dates or timestamps
+    #[arg(long)]
+    parse_dates: bool,
+}
+
+fn parse_source(s: &str) -> Result<(String, String)> {
+    let (name, uri) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expect NAME=URI, got {}", s))?;
+    Ok((name.to_string(), uri.to_string()))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let mut ctx = Context::new();
+    // 结果按 --max-rows 截断而不是报错，见 Config::query_options
+    ctx.set_limits(Limits {
+        max_fetch_bytes: args.max_fetch_bytes,
+        max_rows_loaded: args.max_rows_loaded,
+        max_result_rows: None,
+    });
+    let options = LoadOptions::default().with_parse_dates(args.parse_dates);
+    for (name, uri) in args.sources {
+        ctx.register_source(name, uri, options.clone());
+    }
+
+    let state = Arc::new(State {
+        ctx,
+        config: Config {
+            timeout: Duration::from_secs(args.timeout),
+            max_rows: args.max_rows,
+            max_body_bytes: args.max_body_bytes,
+            allow_copy: args.allow_copy,
+            allow_any_source: args.allow_any_source,
+            pg_password: args.pg_password,
+        },
+    });
+
+    let (addr, pg_addr) = (args.addr, args.pg_addr);
+    if let Some(pg_addr) = pg_addr {
+        if !pg_addr.ip().is_loopback() && state.config.pg_password.is_none() {
+            warn!(
+                "postgres listener on {} accepts any client, consider --pg-password",
+                pg_addr
+            );
+        }
+    }
+    let http = async { Ok::<_, anyhow::Error>(server::serve(addr, state.clone()).await?) };
+    match pg_addr {
+        Some(pg_addr) => {
+            let pg = async { Ok(pgwire::serve(pg_addr, state.clone()).await?) };
+            tokio::try_join!(http, pg)?;
+        }
+        None => http.await?,
+    }
+    Ok(())
+}
@@ -0,0 +1,976 @@
+use crate::server::State;
+use queryer::{AnyValue, DataSet, DataType, Param, Params, QueryError};
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream};
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+const PROTOCOL_VERSION: i32 = 196608;
+const SSL_REQUEST: i32 = 80877103;
+const GSSENC_REQUEST: i32 = 80877104;
+const CANCEL_REQUEST: i32 = 80877102;
+/// 单条消息的长度上限，防止客户端让我们分配过大的内存
+const MAX_MESSAGE_LEN: usize = 64 << 20;
+
+/// 在 addr 上接受 PostgreSQL 客户端的连接，直到出错才返回
+pub async fn serve(addr: SocketAddr, state: Arc<State>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("listening on postgres://{}", addr);
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = Connection::new(stream, state).run().await {
+                warn!("connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// 连接上出的错：io 出错时断开连接，其它的以 ErrorResponse 发给客户端
+#[derive(Debug)]
+enum Error {
+    Io(io::Error),
+    Sql { code: &'static str, message: String },
+}
+
+impl Error {
+    fn sql(code: &'static str, message: impl ToString) -> Self {
+        Error::Sql {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// 把查询出错映射成 SQLSTATE
+impl From<QueryError> for Error {
+    fn from(e: QueryError) -> Self {
+        let code = match e {
+            QueryError::Parse(_) => "42601",
+            QueryError::Unsupported { .. } => "0A000",
+            QueryError::Param(_) => "22023",
+            QueryError::InvalidRegex { .. } => "2201B",
+            QueryError::UnknownColumn { .. } => "42703",
+            QueryError::Fetch { .. } => "58030",
+            QueryError::Load(_) => "22000",
+            QueryError::NotAllowed(_) => "42501",
+            QueryError::LimitExceeded(_) => "54000",
+            QueryError::Timeout(_) | QueryError::Cancelled => "57014",
+            _ => "XX000",
+        };
+        Error::sql(code, e)
+    }
+}
+
+/// 发给客户端的一条消息：类型、长度，然后是内容
+struct Message(Vec<u8>);
+
+impl Message {
+    fn new(tag: u8) -> Self {
+        Self(vec![tag, 0, 0, 0, 0])
+    }
+
+    fn i16(mut self, v: i16) -> Self {
+        self.0.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    fn i32(mut self, v: i32) -> Self {
+        self.0.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    fn bytes(mut self, v: &[u8]) -> Self {
+        self.0.extend_from_slice(v);
+        self
+    }
+
+    fn cstr(self, v: &str) -> Self {
+        self.bytes(v.as_bytes()).bytes(&[0])
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let len = (self.0.len() - 1) as i32;
+        self.0[1..5].copy_from_slice(&len.to_be_bytes());
+        self.0
+    }
+}
+
+/// 读取客户端发来的消息内容
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < n {
+            return Err(Error::sql("08P01", "Message is shorter than expected"));
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16, Error> {
+        Ok(i16::from_be_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn i32(&mut self) -> Result<i32, Error> {
+        let b = self.take(4)?;
+        Ok(i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn cstr(&mut self) -> Result<String, Error> {
+        let end = self
+            .0
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| Error::sql("08P01", "Missing string terminator"))?;
+        let s = String::from_utf8(self.0[..end].to_vec()).map_err(|e| Error::sql("22021", e))?;
+        self.0 = &self.0[end + 1..];
+        Ok(s)
+    }
+
+    /// 长度为 -1 表示 NULL
+    fn value(&mut self) -> Result<Option<&'a [u8]>, Error> {
+        match self.i32()? {
+            len if len < 0 => Ok(None),
+            len => self.take(len as usize).map(Some),
+        }
+    }
+}
+
+/// 执行一个语句的结果
+enum Outcome {
+    /// sent 是已经发出去的行数，notice 是结果被截断时给客户端的提示
+    Rows {
+        data: DataSet,
+        sent: usize,
+        notice: Option<String>,
+    },
+    /// 不返回数据的语句，tag 是 CommandComplete 的标签，notice 是给客户端的提示
+    Command {
+        tag: String,
+        notice: Option<String>,
+    },
+    Empty,
+}
+
+/// Parse 之后得到的预备语句，types 是客户端声明的参数类型
+struct Statement {
+    sql: String,
+    types: Vec<i32>,
+}
+
+/// Bind 之后得到的 portal，第一次 Describe 或者 Execute 时才执行
+struct Portal {
+    sql: String,
+    params: Params,
+    outcome: Option<Outcome>,
+}
+
+/// 一个客户端连接，支持简单查询和扩展查询协议，结果都是文本格式
+pub struct Connection<S> {
+    stream: BufStream<S>,
+    state: Arc<State>,
+    statements: HashMap<String, Statement>,
+    portals: HashMap<String, Portal>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    pub fn new(stream: S, state: Arc<State>) -> Self {
+        Self {
+            stream: BufStream::new(stream),
+            state,
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+        }
+    }
+
+    pub async fn run(mut self) -> io::Result<()> {
+        match self.startup().await {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(e) => return self.fail(e).await,
+        }
+
+        // 扩展查询出错之后，丢掉后面的消息直到 Sync
+        let mut failed = false;
+        while let Some((tag, body)) = self.read_message().await? {
+            if failed && tag != b'S' && tag != b'X' {
+                continue;
+            }
+            let mut body = Reader(&body);
+            let result = match tag {
+                b'Q' => self.simple_query(&mut body).await,
+                b'P' => self.parse(&mut body).await,
+                b'B' => self.bind(&mut body).await,
+                b'D' => self.describe(&mut body).await,
+                b'E' => self.execute(&mut body).await,
+                b'C' => self.close(&mut body).await,
+                b'S' => {
+                    failed = false;
+                    self.ready().await.map_err(Error::from)
+                }
+                b'H' => self.stream.flush().await.map_err(Error::from),
+                b'X' => return Ok(()),
+                _ => Err(Error::sql(
+                    "08P01",
+                    format!("Unsupported message type '{}'", tag as char),
+                )),
+            };
+
+            match result {
+                Ok(()) => {}
+                Err(Error::Io(e)) => return Err(e),
+                Err(Error::Sql { code, message }) => {
+                    self.send(error_response("ERROR", code, &message)).await?;
+                    failed = tag != b'Q';
+                }
+            }
+            if tag == b'Q' {
+                self.ready().await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn fail(mut self, e: Error) -> io::Result<()> {
+        match e {
+            Error::Io(e) => Err(e),
+            Error::Sql { code, message } => {
+                self.send(error_response("FATAL", code, &message)).await?;
+                self.stream.flush().await
+            }
+        }
+    }
+
+    /// 处理启动消息。不支持 SSL，配置了密码时要求客户端用明文密码认证。
+    /// 返回 false 表示不再继续这个连接
+    async fn startup(&mut self) -> Result<bool, Error> {
+        loop {
+            let len = self.stream.read_i32().await? as usize;
+            if !(8..=MAX_MESSAGE_LEN).contains(&len) {
+                return Err(Error::sql("08P01", "Invalid startup message length"));
+            }
+            let mut body = vec![0; len - 4];
+            self.stream.read_exact(&mut body).await?;
+            let mut body = Reader(&body);
+
+            match body.i32()? {
+                SSL_REQUEST | GSSENC_REQUEST => {
+                    self.stream.write_all(b"N").await?;
+                    self.stream.flush().await?;
+                }
+                // 查询没法中途取消，直接关掉这个连接
+                CANCEL_REQUEST => return Ok(false),
+                PROTOCOL_VERSION => {
+                    let mut params = HashMap::new();
+                    while let Ok(key) = body.cstr() {
+                        if key.is_empty() {
+                            break;
+                        }
+                        params.insert(key, body.cstr()?);
+                    }
+                    info!(
+                        "postgres client connected: user={:?} database={:?}",
+                        params.get("user"),
+                        params.get("database")
+                    );
+                    self.authenticate(params.get("user")).await?;
+                    break;
+                }
+                v => {
+                    return Err(Error::sql(
+                        "0A000",
+                        format!("Unsupported protocol version {}.{}", v >> 16, v & 0xffff),
+                    ))
+                }
+            }
+        }
+
+        self.send(Message::new(b'R').i32(0).finish()).await?;
+        for (key, value) in [
+            ("server_version", "14.0"),
+            ("server_encoding", "UTF8"),
+            ("client_encoding", "UTF8"),
+            ("DateStyle", "ISO, MDY"),
+            ("integer_datetimes", "on"),
+            ("standard_conforming_strings", "on"),
+        ] {
+            let msg = Message::new(b'S').cstr(key).cstr(value).finish();
+            self.send(msg).await?;
+        }
+        self.ready().await?;
+        Ok(true)
+    }
+
+    /// 配置了密码时用 MD5 认证：每个连接一个随机的 salt，密码本身不在网络上传输。
+    /// 没有 TLS，能看到流量的人仍然可以离线猜密码，所以密码要足够长
+    async fn authenticate(&mut self, user: Option<&String>) -> Result<(), Error> {
+        let user = user.map_or("", |u| u.as_str());
+        let mut salt = [0; 4];
+        getrandom::getrandom(&mut salt).map_err(io::Error::from)?;
+        let expected = match &self.state.config.pg_password {
+            Some(password) => md5_password(password, user, &salt),
+            None => return Ok(()),
+        };
+        self.send(Message::new(b'R').i32(5).bytes(&salt).finish())
+            .await?;
+        self.stream.flush().await?;
+
+        let response = match self.read_message().await? {
+            Some((b'p', body)) => Reader(&body).cstr()?,
+            Some(_) => return Err(Error::sql("08P01", "Expected a password message")),
+            None => return Err(Error::Io(io::ErrorKind::UnexpectedEof.into())),
+        };
+        if !constant_time_eq(response.as_bytes(), expected.as_bytes()) {
+            warn!("password authentication failed: user={:?}", user);
+            return Err(Error::sql(
+                "28P01",
+                format!("password authentication failed for user {:?}", user),
+            ));
+        }
+        Ok(())
+    }
+
+    /// 读一条消息，客户端断开时返回 None
+    async fn read_message(&mut self) -> io::Result<Option<(u8, Vec<u8>)>> {
+        let tag = match self.stream.read_u8().await {
+            Ok(tag) => tag,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let len = self.stream.read_i32().await? as usize;
+        if !(4..=MAX_MESSAGE_LEN).contains(&len) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid message length",
+            ));
+        }
+        let mut body = vec![0; len - 4];
+        self.stream.read_exact(&mut body).await?;
+        Ok(Some((tag, body)))
+    }
+
+    async fn send(&mut self, msg: Vec<u8>) -> io::Result<()> {
+        self.stream.write_all(&msg).await
+    }
+
+    async fn ready(&mut self) -> io::Result<()> {
+        self.send(Message::new(b'Z').bytes(b"I").finish()).await?;
+        self.stream.flush().await
+    }
+
+    async fn simple_query(&mut self, body: &mut Reader<'_>) -> Result<(), Error> {
+        let sql = body.cstr()?;
+        let mut outcome = run(&self.state, &sql, Params::new()).await?;
+        if let Outcome::Rows { data, .. } = &outcome {
+            self.send(row_description(data)).await?;
+        }
+        self.send_rows(&mut outcome, 0).await
+    }
+
+    async fn parse(&mut self, body: &mut Reader<'_>) -> Result<(), Error> {
+        let name = body.cstr()?;
+        let sql = body.cstr()?;
+        let types = (0..body.i16()?)
+            .map(|_| body.i32())
+            .collect::<Result<_, _>>()?;
+        self.statements.insert(name, Statement { sql, types });
+        self.send(Message::new(b'1').finish()).await?;
+        Ok(())
+    }
+
+    async fn bind(&mut self, body: &mut Reader<'_>) -> Result<(), Error> {
+        let portal = body.cstr()?;
+        let name = body.cstr()?;
+        let statement = self
+            .statements
+            .get(&name)
+            .ok_or_else(|| Error::sql("26000", format!("Unknown statement {:?}", name)))?;
+
+        let formats: Vec<i16> = (0..body.i16()?)
+            .map(|_| body.i16())
+            .collect::<Result<_, _>>()?;
+        let mut params = Params::new();
+        for i in 0..body.i16()? as usize {
+            // 只有一个格式时所有参数都用它
+            let format = formats.get(i).or_else(|| formats.first()).unwrap_or(&0);
+            if *format != 0 {
+                return Err(Error::sql("0A000", "Binary parameters are not supported"));
+            }
+            let oid = statement.types.get(i).copied().unwrap_or(0);
+            params = params.push(param(body.value()?, oid)?);
+        }
+        if (0..body.i16()?).any(|_| !matches!(body.i16(), Ok(0))) {
+            return Err(Error::sql("0A000", "Binary results are not supported"));
+        }
+
+        let sql = statement.sql.clone();
+        self.portals.insert(
+            portal,
+            Portal {
+                sql,
+                params,
+                outcome: None,
+            },
+        );
+        self.send(Message::new(b'2').finish()).await?;
+        Ok(())
+    }
+
+    /// 描述预备语句时只返回参数的类型和 NoData，不执行语句；结果的列要等 Bind 之后描述 portal 时
+    /// 才知道，那时用真实的参数执行一次，结果留给后面的 Execute
+    async fn describe(&mut self, body: &mut Reader<'_>) -> Result<(), Error> {
+        let kind = body.u8()?;
+        let name = body.cstr()?;
+
+        if kind == b'S' {
+            let statement = self
+                .statements
+                .get(&name)
+                .ok_or_else(|| Error::sql("26000", format!("Unknown statement {:?}", name)))?;
+            let count = placeholders(&statement.sql).max(statement.types.len());
+            let mut msg = Message::new(b't').i16(count as i16);
+            for i in 0..count {
+                // 没声明类型的参数按文本处理，绑定时再推断
+                let oid = statement.types.get(i).copied().unwrap_or(0);
+                msg = msg.i32(if oid == 0 { 25 } else { oid });
+            }
+            self.send(msg.finish()).await?;
+            return Ok(self.send(Message::new(b'n').finish()).await?);
+        }
+
+        let mut portal = self.portal(&name)?;
+        let outcome = match portal.outcome.take() {
+            Some(outcome) => outcome,
+            None => run(&self.state, &portal.sql, portal.params.clone()).await?,
+        };
+        let msg = describe_outcome(&outcome);
+        portal.outcome = Some(outcome);
+        self.portals.insert(name, portal);
+        Ok(self.send(msg).await?)
+    }
+
+    async fn execute(&mut self, body: &mut Reader<'_>) -> Result<(), Error> {
+        let name = body.cstr()?;
+        let limit = body.i32()?.max(0) as usize;
+
+        let mut portal = self.portal(&name)?;
+        let mut outcome = match portal.outcome.take() {
+            Some(outcome) => outcome,
+            None => run(&self.state, &portal.sql, portal.params.clone()).await?,
+        };
+        self.send_rows(&mut outcome, limit).await?;
+        portal.outcome = Some(outcome);
+        self.portals.insert(name, portal);
+        Ok(())
+    }
+
+    async fn close(&mut self, body: &mut Reader<'_>) -> Result<(), Error> {
+        let kind = body.u8()?;
+        let name = body.cstr()?;
+        if kind == b'S' {
+            self.statements.remove(&name);
+        } else {
+            self.portals.remove(&name);
+        }
+        self.send(Message::new(b'3').finish()).await?;
+        Ok(())
+    }
+
+    fn portal(&mut self, name: &str) -> Result<Portal, Error> {
+        self.portals
+            .remove(name)
+            .ok_or_else(|| Error::sql("34000", format!("Unknown portal {:?}", name)))
+    }
+
+    /// 发送结果里的行，limit 为 0 时全部发完。行写进缓冲区，缓冲区满了就发出去
+    async fn send_rows(&mut self, outcome: &mut Outcome, limit: usize) -> Result<(), Error> {
+        let (data, sent, notice) = match outcome {
+            Outcome::Rows { data, sent, notice } => (data, sent, notice),
+            Outcome::Command { tag, notice } => {
+                if let Some(notice) = notice.take() {
+                    self.send(notice_response(&notice)).await?;
+                }
+                let msg = Message::new(b'C').cstr(tag).finish();
+                return Ok(self.send(msg).await?);
+            }
+            Outcome::Empty => return Ok(self.send(Message::new(b'I').finish()).await?),
+        };
+
+        let start = *sent;
+        let end = match limit {
+            0 => data.height(),
+            n => (start + n).min(data.height()),
+        };
+        for row in start..end {
+            let mut msg = Message::new(b'D').i16(data.width() as i16);
+            for column in data.get_columns() {
+                msg = match text(column.get(row)) {
+                    Some(v) => msg.i32(v.len() as i32).bytes(v.as_bytes()),
+                    None => msg.i32(-1),
+                };
+            }
+            self.stream.write_all(&msg.finish()).await?;
+        }
+        *sent = end;
+
+        if end < data.height() {
+            return Ok(self.send(Message::new(b's').finish()).await?);
+        }
+        if let Some(notice) = notice.take() {
+            self.send(notice_response(&notice)).await?;
+        }
+        let tag = format!("SELECT {}", end - start);
+        self.send(Message::new(b'C').cstr(&tag).finish()).await?;
+        Ok(())
+    }
+}
+
+/// 执行一个语句。事务相关的语句直接返回成功；会话设置不支持，返回成功，
+/// 但是提示客户端设置没有生效（驱动连接时常会发 SET，直接报错会连不上），其它的交给 queryer
+async fn run(state: &State, sql: &str, params: Params) -> Result<Outcome, Error> {
+    let sql = sql.trim().trim_end_matches(';').trim();
+    if sql.is_empty() {
+        return Ok(Outcome::Empty);
+    }
+    let keyword = sql
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_uppercase();
+    match keyword.as_str() {
+        "BEGIN" | "COMMIT" | "ROLLBACK" => {
+            return Ok(Outcome::Command {
+                tag: keyword,
+                notice: None,
+            })
+        }
+        "SET" | "RESET" => {
+            let notice = format!("{} has no effect, session settings are not supported", sql);
+            return Ok(Outcome::Command {
+                tag: keyword,
+                notice: Some(notice),
+            });
+        }
+        _ => {}
+    }
+
+    info!("query: {}", sql);
+    let options = state.config.query_options();
+    let ds = state.ctx.query_with_options(sql, params, options).await?;
+
+    let max_rows = state.config.max_rows;
+    if ds.height() > max_rows {
+        return Ok(Outcome::Rows {
+            data: DataSet::from(ds.head(Some(max_rows))),
+            sent: 0,
+            notice: Some(format!("Result truncated to {} rows", max_rows)),
+        });
+    }
+    Ok(Outcome::Rows {
+        data: ds,
+        sent: 0,
+        notice: None,
+    })
+}
+
+fn describe_outcome(outcome: &Outcome) -> Vec<u8> {
+    match outcome {
+        Outcome::Rows { data, .. } => row_description(data),
+        _ => Message::new(b'n').finish(),
+    }
+}
+
+fn row_description(ds: &DataSet) -> Vec<u8> {
+    let mut msg = Message::new(b'T').i16(ds.width() as i16);
+    for column in ds.get_columns() {
+        let (oid, len) = pg_type(column.dtype());
+        msg = msg
+            .cstr(column.name())
+            .i32(0)
+            .i16(0)
+            .i32(oid)
+            .i16(len)
+            .i32(-1)
+            .i16(0);
+    }
+    msg.finish()
+}
+
+fn error_response(severity: &str, code: &str, message: &str) -> Vec<u8> {
+    Message::new(b'E')
+        .bytes(b"S")
+        .cstr(severity)
+        .bytes(b"V")
+        .cstr(severity)
+        .bytes(b"C")
+        .cstr(code)
+        .bytes(b"M")
+        .cstr(message)
+        .bytes(&[0])
+        .finish()
+}
+
+/// MD5 认证时客户端发回的内容：`md5` 加上 md5(md5(password + user) + salt) 的十六进制
+fn md5_password(password: &str, user: &str, salt: &[u8]) -> String {
+    let inner = format!("{:x}", md5::compute(format!("{}{}", password, user)));
+    let mut outer = inner.into_bytes();
+    outer.extend_from_slice(salt);
+    format!("md5{:x}", md5::compute(outer))
+}
+
+/// 比较用的时间只和长度有关，不会暴露从第几个字节开始不同
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn notice_response(message: &str) -> Vec<u8> {
+    Message::new(b'N')
+        .bytes(b"SWARNING\0VWARNING\0C01000\0M")
+        .cstr(message)
+        .bytes(&[0])
+        .finish()
+}
+
+/// polars 的类型对应的 PostgreSQL 类型 OID 和长度，没有对应类型的都当成 text
+fn pg_type(dtype: &DataType) -> (i32, i16) {
+    match dtype {
+        DataType::Boolean => (16, 1),
+        DataType::Int8 | DataType::Int16 | DataType::UInt8 => (21, 2),
+        DataType::Int32 | DataType::UInt16 => (23, 4),
+        DataType::Int64 | DataType::UInt32 => (20, 8),
+        DataType::UInt64 => (1700, -1),
+        DataType::Float32 => (700, 4),
+        DataType::Float64 => (701, 8),
+        DataType::Date32 => (1082, 4),
+        DataType::Date64 => (1114, 8),
+        DataType::Time64(_) => (1083, 8),
+        _ => (25, -1),
+    }
+}
+
+/// 值的文本格式，NULL 返回 None
+fn text(v: AnyValue) -> Option<String> {
+    let float = |v: f64| {
+        if v.is_nan() {
+            "NaN".to_string()
+        } else if v.is_infinite() {
+            if v > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+        } else {
+            v.to_string()
+        }
+    };
+    Some(match v {
+        AnyValue::Null => return None,
+        AnyValue::Boolean(v) => if v { "t" } else { "f" }.to_string(),
+        AnyValue::Utf8(v) => v.to_string(),
+        AnyValue::Float32(v) if v.is_finite() => v.to_string(),
+        AnyValue::Float32(v) => float(v as f64),
+        AnyValue::Float64(v) => float(v),
+        AnyValue::Duration(v, _) => v.to_string(),
+        v => v.to_string(),
+    })
+}
+
+/// 把文本格式的参数按声明的类型转换，没声明类型时按内容推断
+fn param(value: Option<&[u8]>, oid: i32) -> Result<Param, Error> {
+    let value = match value {
+        Some(v) => std::str::from_utf8(v).map_err(|e| Error::sql("22021", e))?,
+        None => return Ok(Param::Null),
+    };
+    let invalid = |_| Error::sql("22P02", format!("Invalid parameter value {:?}", value));
+    Ok(match oid {
+        16 => match value.to_lowercase().as_str() {
+            "t" | "true" | "yes" | "on" | "1" => Param::Boolean(true),
+            "f" | "false" | "no" | "off" | "0" => Param::Boolean(false),
+            _ => return Err(invalid(())),
+        },
+        20 | 21 | 23 => Param::Int64(value.parse().map_err(|_| invalid(()))?),
+        700 | 701 | 1700 => Param::Float64(value.parse().map_err(|_| invalid(()))?),
+        0 => match (value.parse(), value.parse()) {
+            (Ok(v), _) => Param::Int64(v),
+            (_, Ok(v)) => Param::Float64(v),
+            _ => Param::Utf8(value.to_string()),
+        },
+        _ => Param::Utf8(value.to_string()),
+    })
+}
+
+/// SQL 里 `$n` 占位符的个数（最大的 n），跳过字符串和带引号的标识符
+fn placeholders(sql: &str) -> usize {
+    let mut count = 0;
+    let mut quote = None;
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '$') => {
+                let mut n = String::new();
+                while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    n.push(*d);
+                    chars.next();
+                }
+                count = count.max(n.parse().unwrap_or(0));
+            }
+            _ => {}
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::tests::source;
+    use crate::server::Config;
+    use queryer::{Context, LoadOptions};
+    use tempfile::NamedTempFile;
+    use tokio::io::DuplexStream;
+
+    fn connect() -> (DuplexStream, NamedTempFile) {
+        connect_with(Config::default())
+    }
+
+    /// 返回的临时文件是数据源，要和连接活得一样久
+    fn connect_with(config: Config) -> (DuplexStream, NamedTempFile) {
+        let file = source();
+        let mut ctx = Context::new();
+        let uri = format!("file://{}", file.path().display());
+        ctx.register_source("t", uri, LoadOptions::default());
+        let state = Arc::new(State { ctx, config });
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(Connection::new(server, state).run());
+        (client, file)
+    }
+
+    async fn write(client: &mut DuplexStream, msg: Message) {
+        client.write_all(&msg.finish()).await.unwrap();
+    }
+
+    async fn read(client: &mut DuplexStream) -> (u8, Vec<u8>) {
+        let tag = client.read_u8().await.unwrap();
+        let mut body = vec![0; client.read_i32().await.unwrap() as usize - 4];
+        client.read_exact(&mut body).await.unwrap();
+        (tag, body)
+    }
+
+    /// 读消息直到 ReadyForQuery
+    async fn read_until_ready(client: &mut DuplexStream) -> Vec<(u8, Vec<u8>)> {
+        let mut messages = Vec::new();
+        loop {
+            let (tag, body) = read(client).await;
+            messages.push((tag, body));
+            if tag == b'Z' {
+                return messages;
+            }
+        }
+    }
+
+    async fn send_startup(client: &mut DuplexStream) {
+        let body = Message(Vec::new())
+            .i32(PROTOCOL_VERSION)
+            .cstr("user")
+            .cstr("test")
+            .bytes(&[0])
+            .0;
+        client
+            .write_all(&(body.len() as i32 + 4).to_be_bytes())
+            .await
+            .unwrap();
+        client.write_all(&body).await.unwrap();
+    }
+
+    async fn startup(client: &mut DuplexStream) {
+        send_startup(client).await;
+        let messages = read_until_ready(client).await;
+        assert_eq!(messages[0], (b'R', vec![0, 0, 0, 0]));
+    }
+
+    fn tags(messages: &[(u8, Vec<u8>)]) -> String {
+        messages.iter().map(|(tag, _)| *tag as char).collect()
+    }
+
+    fn data_row(body: &[u8]) -> Vec<Option<String>> {
+        let mut body = Reader(body);
+        (0..body.i16().unwrap())
+            .map(|_| {
+                let v = body.value().unwrap()?;
+                Some(String::from_utf8(v.to_vec()).unwrap())
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn simple_query_works() {
+        let (mut client, _file) = connect();
+        startup(&mut client).await;
+
+        let sql = "SELECT name, value, value > 2 AS big FROM t WHERE value > 1";
+        write(&mut client, Message::new(b'Q').cstr(sql)).await;
+        let messages = read_until_ready(&mut client).await;
+        assert_eq!(tags(&messages), "TDDCZ");
+
+        let mut desc = Reader(&messages[0].1);
+        assert_eq!(desc.i16().unwrap(), 3);
+        let mut oids = Vec::new();
+        for _ in 0..3 {
+            desc.cstr().unwrap();
+            desc.take(6).unwrap();
+            oids.push(desc.i32().unwrap());
+            desc.take(8).unwrap();
+        }
+        assert_eq!(oids, vec![25, 20, 16]);
+        assert_eq!(
+            data_row(&messages[2].1),
+            vec![Some("c".into()), Some("3".into()), Some("t".into())]
+        );
+        assert_eq!(messages[3].1, b"SELECT 2\0");
+
+        write(&mut client, Message::new(b'Q').cstr("SELECT nmae FROM t")).await;
+        let messages = read_until_ready(&mut client).await;
+        assert_eq!(tags(&messages), "EZ");
+        assert!(messages[0].1.windows(6).any(|w| w == b"C42703"));
+    }
+
+    #[tokio::test]
+    async fn extended_query_works() {
+        let (mut client, _file) = connect();
+        startup(&mut client).await;
+
+        // LIMIT 的参数是 NULL 时没法执行，描述预备语句时不应该执行它
+        let sql = "SELECT name FROM t WHERE value >= $1 ORDER BY name LIMIT $2";
+        write(&mut client, Message::new(b'P').cstr("s").cstr(sql).i16(0)).await;
+        write(&mut client, Message::new(b'D').bytes(b"S").cstr("s")).await;
+        let bind = Message::new(b'B')
+            .cstr("")
+            .cstr("s")
+            .i16(0)
+            .i16(2)
+            .i32(1)
+            .bytes(b"2")
+            .i32(1)
+            .bytes(b"5")
+            .i16(0);
+        write(&mut client, bind).await;
+        write(&mut client, Message::new(b'D').bytes(b"P").cstr("")).await;
+        write(&mut client, Message::new(b'E').cstr("").i32(1)).await;
+        write(&mut client, Message::new(b'E').cstr("").i32(0)).await;
+        write(&mut client, Message::new(b'S')).await;
+
+        let messages = read_until_ready(&mut client).await;
+        assert_eq!(tags(&messages), "1tn2TDsDCZ");
+        assert_eq!(messages[1].1, vec![0, 2, 0, 0, 0, 25, 0, 0, 0, 25]);
+        assert_eq!(data_row(&messages[5].1), vec![Some("b".into())]);
+        assert_eq!(data_row(&messages[7].1), vec![Some("c".into())]);
+        assert_eq!(messages[8].1, b"SELECT 1\0");
+
+        // 出错之后忽略到 Sync 为止的消息
+        write(&mut client, Message::new(b'E').cstr("missing").i32(0)).await;
+        write(&mut client, Message::new(b'E').cstr("").i32(0)).await;
+        write(&mut client, Message::new(b'S')).await;
+        assert_eq!(tags(&read_until_ready(&mut client).await), "EZ");
+    }
+
+    #[tokio::test]
+    async fn password_and_restrictions_are_enforced() {
+        let config = Config {
+            pg_password: Some("secret".into()),
+            ..Config::default()
+        };
+        let sqlstate = |body: &[u8], code: &[u8]| body.windows(code.len()).any(|w| w == code);
+
+        // 服务端要求 MD5 认证，返回 salt
+        let request_salt = |(tag, body): (u8, Vec<u8>)| {
+            assert_eq!((tag, &body[..4]), (b'R', &[0, 0, 0, 5][..]));
+            body[4..].to_vec()
+        };
+
+        let (mut client, _file) = connect_with(config.clone());
+        send_startup(&mut client).await;
+        let salt = request_salt(read(&mut client).await);
+        let response = md5_password("wrong", "test", &salt);
+        write(&mut client, Message::new(b'p').cstr(&response)).await;
+        let (tag, body) = read(&mut client).await;
+        assert_eq!(tag, b'E');
+        assert!(sqlstate(&body, b"C28P01"));
+
+        let (mut client, file) = connect_with(config);
+        send_startup(&mut client).await;
+        let salt = request_salt(read(&mut client).await);
+        let response = md5_password("secret", "test", &salt);
+        write(&mut client, Message::new(b'p').cstr(&response)).await;
+        let messages = read_until_ready(&mut client).await;
+        assert_eq!(messages[0], (b'R', vec![0, 0, 0, 0]));
+
+        // 和 HTTP 一样，默认不能写文件，也不能直接读没有注册的数据源
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("out.csv");
+        for sql in [
+            format!("COPY (SELECT name FROM t) TO 'file://{}'", target.display()),
+            format!("SELECT name FROM 'file://{}'", file.path().display()),
+        ] {
+            write(&mut client, Message::new(b'Q').cstr(&sql)).await;
+            let messages = read_until_ready(&mut client).await;
+            assert_eq!(tags(&messages), "EZ");
+            assert!(sqlstate(&messages[0].1, b"C42501"));
+        }
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn md5_password_works() {
+        assert_eq!(
+            md5_password("secret", "test", &[1, 2, 3, 4]),
+            "md5594f15006e55e17dff918116ee00778f"
+        );
+        assert!(constant_time_eq(b"md5abc", b"md5abc"));
+        assert!(!constant_time_eq(b"md5abc", b"md5abd"));
+        assert!(!constant_time_eq(b"md5abc", b"md5ab"));
+    }
+
+    #[tokio::test]
+    async fn set_returns_notice() {
+        let (mut client, _file) = connect();
+        startup(&mut client).await;
+
+        write(
+            &mut client,
+            Message::new(b'Q').cstr("SET statement_timeout = 1000"),
+        )
+        .await;
+        let messages = read_until_ready(&mut client).await;
+        assert_eq!(tags(&messages), "NCZ");
+        let notice = String::from_utf8_lossy(&messages[0].1).to_string();
+        assert!(notice.contains("has no effect"), "{}", notice);
+    }
+
+    #[test]
+    fn placeholders_works() {
+        assert_eq!(placeholders("SELECT * FROM t WHERE a = $2 AND b = $1"), 2);
+        assert_eq!(placeholders("SELECT '$3' FROM t WHERE \"$4\" = $1"), 1);
+    }
+}
@@ -0,0 +1,124 @@
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Body, Response, StatusCode};
+use queryer::{DataSet, Format, QueryError};
+use serde_json::json;
+
+/// 响应的格式，由请求的 Accept 头决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// JSON 数组，每行一个对象
+    Json,
+    /// 每行一个 JSON 对象
+    NdJson,
+    Csv,
+    /// Arrow IPC 文件格式
+    Arrow,
+}
+
+impl Encoding {
+    /// 按 Accept 里列出的顺序找第一个支持的类型，没有 Accept 时返回 JSON
+    pub fn negotiate(accept: Option<&str>) -> Option<Self> {
+        let accept = match accept {
+            Some(accept) => accept,
+            None => return Some(Encoding::Json),
+        };
+        accept.split(',').find_map(|item| {
+            let mime = item.split(';').next().unwrap_or_default().trim();
+            match mime.to_lowercase().as_str() {
+                "application/json" | "application/*" | "*/*" => Some(Encoding::Json),
+                "application/x-ndjson" => Some(Encoding::NdJson),
+                "text/csv" | "text/*" => Some(Encoding::Csv),
+                "application/vnd.apache.arrow.file" | "application/vnd.apache.arrow" => {
+                    Some(Encoding::Arrow)
+                }
+                _ => None,
+            }
+        })
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Encoding::Json => "application/json",
+            Encoding::NdJson => "application/x-ndjson",
+            Encoding::Csv => "text/csv",
+            Encoding::Arrow => "application/vnd.apache.arrow.file",
+        }
+    }
+
+    pub fn encode(&self, ds: &DataSet) -> Result<Vec<u8>, QueryError> {
+        match self {
+            Encoding::Json => {
+                let lines = String::from_utf8(ds.serialize(Format::Json)?)
+                    .map_err(QueryError::execution)?;
+                let rows: Vec<&str> = lines.lines().filter(|l| !l.is_empty()).collect();
+                Ok(format!("[{}]", rows.join(",")).into_bytes())
+            }
+            Encoding::NdJson => ds.serialize(Format::Json),
+            Encoding::Csv => ds.serialize(Format::Csv),
+            Encoding::Arrow => ds.serialize(Format::Ipc),
+        }
+    }
+
+    pub fn response(&self, body: Vec<u8>) -> Response<Body> {
+        let mut response = Response::new(Body::from(body));
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(self.content_type()));
+        response
+    }
+}
+
+/// 出错时返回的 JSON：`{"error": {"kind": "...", "message": "..."}}`
+pub fn error(status: StatusCode, kind: &str, message: impl ToString) -> Response<Body> {
+    let body = json!({
+        "error": {
+            "kind": kind,
+            "message": message.to_string(),
+        }
+    });
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+/// 把查询出错映射成对应的状态码和错误类型
+pub fn query_error(e: &QueryError) -> Response<Body> {
+    let (status, kind) = match e {
+        QueryError::Parse(_) => (StatusCode::BAD_REQUEST, "parse"),
+        QueryError::Unsupported { .. } => (StatusCode::BAD_REQUEST, "unsupported"),
+        QueryError::Param(_) => (StatusCode::BAD_REQUEST, "param"),
+        QueryError::InvalidRegex { .. } => (StatusCode::BAD_REQUEST, "invalid_regex"),
+        QueryError::UnknownColumn { .. } => (StatusCode::BAD_REQUEST, "unknown_column"),
+        QueryError::Fetch { .. } => (StatusCode::BAD_GATEWAY, "fetch"),
+        QueryError::Load(_) => (StatusCode::UNPROCESSABLE_ENTITY, "load"),
+        QueryError::NotAllowed(_) => (StatusCode::FORBIDDEN, "not_allowed"),
+        QueryError::LimitExceeded(_) => (StatusCode::UNPROCESSABLE_ENTITY, "limit_exceeded"),
+        QueryError::Timeout(_) => (StatusCode::GATEWAY_TIMEOUT, "timeout"),
+        QueryError::Cancelled => (StatusCode::SERVICE_UNAVAILABLE, "cancelled"),
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, "execution"),
+    };
+    error(status, kind, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate_works() {
+        assert_eq!(Encoding::negotiate(None), Some(Encoding::Json));
+        assert_eq!(
+            Encoding::negotiate(Some("text/html, text/csv;q=0.9")),
+            Some(Encoding::Csv)
+        );
+        assert_eq!(
+            Encoding::negotiate(Some("application/vnd.apache.arrow.file")),
+            Some(Encoding::Arrow)
+        );
+        assert_eq!(Encoding::negotiate(Some("*/*")), Some(Encoding::Json));
+        assert_eq!(Encoding::negotiate(Some("image/png")), None);
+    }
+}
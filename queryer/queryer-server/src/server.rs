@@ -0,0 +1,365 @@
+use crate::response::{self, Encoding};
+use hyper::body::HttpBody;
+use hyper::header::{HeaderValue, ACCEPT, CONTENT_LENGTH};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use queryer::{Context, DataSet, Params, QueryOptions, QueryStats};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// 结果行数超过 max_rows 被截断时，响应里带上这个头
+pub const TRUNCATED_HEADER: &str = "x-queryer-truncated";
+/// 各个阶段的耗时
+pub const SERVER_TIMING: &str = "server-timing";
+
+/// 服务的配置
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// 单个查询最多执行多久
+    pub timeout: Duration,
+    /// 最多返回多少行
+    pub max_rows: usize,
+    /// 请求体（也就是 SQL）最多多少字节
+    pub max_body_bytes: usize,
+    /// 是否允许 `COPY ... TO`，它会在服务器上写文件
+    pub allow_copy: bool,
+    /// 是否允许在 SQL 里直接写文件路径或者网址，否则只能查询注册过的数据源
+    pub allow_any_source: bool,
+    /// PostgreSQL 客户端连接时要提供的密码，`None` 表示不需要认证
+    pub pg_password: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_rows: 10_000,
+            max_body_bytes: 1 << 20,
+            allow_copy: false,
+            allow_any_source: false,
+            pg_password: None,
+        }
+    }
+}
+
+impl Config {
+    /// 按配置执行查询用的选项。结果多取一行，用来判断是不是被截断了
+    pub fn query_options(&self) -> QueryOptions {
+        QueryOptions::default()
+            .with_timeout(self.timeout)
+            .with_row_limit(self.max_rows.saturating_add(1))
+            .with_read_only(!self.allow_copy)
+            .with_registered_sources_only(!self.allow_any_source)
+    }
+}
+
+/// 所有请求共享的状态
+#[derive(Debug)]
+pub struct State {
+    pub ctx: Context,
+    pub config: Config,
+}
+
+/// 在 addr 上启动服务，直到出错才返回
+pub async fn serve(addr: SocketAddr, state: Arc<State>) -> hyper::Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(&state, req).await) }
+            }))
+        }
+    });
+
+    info!("listening on http://{}", addr);
+    hyper::Server::bind(&addr).serve(make_service).await
+}
+
+/// 处理一个请求。目前只有 `POST /query`，请求体是 SQL
+pub async fn handle(state: &State, req: Request<Body>) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        (&Method::POST, "/query") => query(state, req).await,
+        (_, "/query") => response::error(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "invalid_request",
+            "Only POST is allowed",
+        ),
+        (_, path) => response::error(
+            StatusCode::NOT_FOUND,
+            "invalid_request",
+            format!("Unknown path {}", path),
+        ),
+    }
+}
+
+async fn query(state: &State, req: Request<Body>) -> Response<Body> {
+    let accept = req.headers().get(ACCEPT).and_then(|v| v.to_str().ok());
+    let encoding = match Encoding::negotiate(accept) {
+        Some(encoding) => encoding,
+        None => {
+            return response::error(
+                StatusCode::NOT_ACCEPTABLE,
+                "invalid_request",
+                "Supported types: application/json, application/x-ndjson, text/csv, \
+                 application/vnd.apache.arrow.file",
+            )
+        }
+    };
+
+    let body = match read_body(req, state.config.max_body_bytes).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+    let sql = match std::str::from_utf8(&body) {
+        Ok(sql) if !sql.trim().is_empty() => sql.trim().trim_end_matches(';'),
+        Ok(_) => return response::error(StatusCode::BAD_REQUEST, "invalid_request", "Empty query"),
+        Err(e) => return response::error(StatusCode::BAD_REQUEST, "invalid_request", e),
+    };
+
+    info!("query: {}", sql);
+    let (ds, mut stats) = match state
+        .ctx
+        .query_with_stats(sql, Params::new(), state.config.query_options())
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => return response::query_error(&e),
+    };
+
+    let truncated = ds.height() > state.config.max_rows;
+    let ds = if truncated {
+        DataSet::from(ds.head(Some(state.config.max_rows)))
+    } else {
+        ds
+    };
+
+    let start = Instant::now();
+    let body = encoding.encode(&ds);
+    stats.serialize = start.elapsed();
+    info!(
+        rows = stats.rows_returned,
+        bytes_fetched = stats.bytes_fetched,
+        total_ms = stats.total().as_secs_f64() * 1000.0,
+        "query finished"
+    );
+
+    match body {
+        Ok(body) => {
+            let mut response = encoding.response(body);
+            if let Ok(timing) = HeaderValue::from_str(&server_timing(&stats)) {
+                response.headers_mut().insert(SERVER_TIMING, timing);
+            }
+            if truncated {
+                response
+                    .headers_mut()
+                    .insert(TRUNCATED_HEADER, HeaderValue::from_static("true"));
+            }
+            response
+        }
+        Err(e) => response::query_error(&e),
+    }
+}
+
+/// 读取请求体，超过 max 字节时返回 413，Content-Length 已经超过时不用读
+async fn read_body(req: Request<Body>, max: usize) -> Result<Vec<u8>, Response<Body>> {
+    let too_large = || {
+        response::error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "invalid_request",
+            format!("Query is larger than {} bytes", max),
+        )
+    };
+    let length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if matches!(length, Some(length) if length > max as u64) {
+        return Err(too_large());
+    }
+
+    let mut body = req.into_body();
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk =
+            chunk.map_err(|e| response::error(StatusCode::BAD_REQUEST, "invalid_request", e))?;
+        if buf.len() + chunk.len() > max {
+            return Err(too_large());
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf)
+}
+
+/// 把各个阶段的耗时写成 Server-Timing 头，浏览器的开发者工具可以直接显示
+fn server_timing(stats: &QueryStats) -> String {
+    let phases = [
+        ("parse", stats.parse),
+        ("plan", stats.plan),
+        ("fetch", stats.fetch),
+        ("load", stats.load),
+        ("execute", stats.execute),
+        ("serialize", stats.serialize),
+    ];
+    let mut timing: Vec<String> = phases
+        .iter()
+        .map(|(name, d)| format!("{};dur={:.3}", name, d.as_secs_f64() * 1000.0))
+        .collect();
+    if stats.cached {
+        timing.push("cache;desc=hit".into());
+    }
+    timing.join(", ")
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use queryer::LoadOptions;
+    use serde_json::Value;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    pub(crate) fn source() -> NamedTempFile {
+        let mut file = tempfile::Builder::new().suffix(".csv").tempfile().unwrap();
+        file.write_all(b"name,value\na,1\nb,2\nc,3\n").unwrap();
+        file
+    }
+
+    /// 返回的临时文件是数据源，要和 State 活得一样久
+    fn state(max_rows: usize) -> (State, NamedTempFile) {
+        let file = source();
+        let mut ctx = Context::new();
+        let uri = format!("file://{}", file.path().display());
+        ctx.register_source("t", uri, LoadOptions::default());
+        let state = State {
+            ctx,
+            config: Config {
+                max_rows,
+                ..Config::default()
+            },
+        };
+        (state, file)
+    }
+
+    fn request(sql: &str, accept: Option<&str>) -> Request<Body> {
+        let mut builder = Request::post("/query");
+        if let Some(accept) = accept {
+            builder = builder.header(ACCEPT, accept);
+        }
+        builder.body(Body::from(sql.to_string())).unwrap()
+    }
+
+    async fn body(response: Response<Body>) -> Vec<u8> {
+        hyper::body::to_bytes(response.into_body())
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn query_returns_requested_format() {
+        let (state, _file) = state(10);
+
+        let response = handle(&state, request("SELECT name FROM t WHERE value > 1", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let timing = response.headers()[SERVER_TIMING].to_str().unwrap();
+        assert!(timing.starts_with("parse;dur=") && timing.contains("execute;dur="));
+        let rows: Value = serde_json::from_slice(&body(response).await).unwrap();
+        assert_eq!(rows, serde_json::json!([{"name": "b"}, {"name": "c"}]));
+
+        let sql = "SELECT name FROM t ORDER BY value DESC";
+        let response = handle(&state, request(sql, Some("text/csv"))).await;
+        assert_eq!(response.headers()["content-type"], "text/csv");
+        assert_eq!(body(response).await, b"name\nc\nb\na\n");
+
+        let accept = Some("application/vnd.apache.arrow.file");
+        let response = handle(&state, request(sql, accept)).await;
+        assert!(body(response).await.starts_with(b"ARROW1"));
+    }
+
+    #[tokio::test]
+    async fn query_limits_rows_and_reports_errors() {
+        let (state, _file) = state(2);
+
+        let response = handle(&state, request("SELECT name FROM t", Some("text/csv"))).await;
+        assert_eq!(response.headers()[TRUNCATED_HEADER], "true");
+        assert_eq!(body(response).await, b"name\na\nb\n");
+
+        let response = handle(&state, request("SELECT nmae FROM t", None)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let error: Value = serde_json::from_slice(&body(response).await).unwrap();
+        assert_eq!(error["error"]["kind"], "unknown_column");
+
+        let response = handle(&state, request("SELECT name FROM t", Some("image/png"))).await;
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+
+        let req = Request::get("/query").body(Body::empty()).unwrap();
+        assert_eq!(
+            handle(&state, req).await.status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+    }
+
+    #[tokio::test]
+    async fn large_body_is_rejected() {
+        let (mut state, _file) = state(10);
+        state.config.max_body_bytes = 20;
+
+        let response = handle(&state, request("SELECT name FROM t", None)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let sql = "SELECT name FROM t WHERE value > 1";
+        let response = handle(&state, request(sql, None)).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let mut req = request(sql, None);
+        req.headers_mut().insert(CONTENT_LENGTH, sql.len().into());
+        assert_eq!(
+            handle(&state, req).await.status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        // 没有 Content-Length 的分块请求也一样
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..3 {
+                sender.send_data("SELECT name ".into()).await.unwrap();
+            }
+        });
+        let req = Request::post("/query").body(body).unwrap();
+        assert_eq!(
+            handle(&state, req).await.status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[tokio::test]
+    async fn copy_and_unregistered_sources_are_rejected() {
+        let (state, file) = state(10);
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("out.csv");
+        let copy = format!("COPY (SELECT name FROM t) TO 'file://{}'", target.display());
+        let direct = format!("SELECT name FROM 'file://{}'", file.path().display());
+
+        for sql in [&copy, &direct] {
+            let response = handle(&state, request(sql, None)).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            let error: Value = serde_json::from_slice(&body(response).await).unwrap();
+            assert_eq!(error["error"]["kind"], "not_allowed");
+        }
+        assert!(!target.exists());
+
+        // 显式打开之后才允许
+        let mut state = state;
+        state.config.allow_copy = true;
+        state.config.allow_any_source = true;
+        for sql in [&copy, &direct] {
+            let response = handle(&state, request(sql, None)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        assert!(target.exists());
+    }
+}
@@ -10,16 +10,17 @@ async fn main() -> Result<()> {
     let data = reqwest::get(url).await?.text().await?;
 
     let df = CsvReader::new(Cursor::new(data))
-                .infer_schema(Some(16))
-                .finish()?;
+        .infer_schema(Some(16))
+        .finish()?;
 
     let filtered = df.filter(&df["new_deaths"].gt(500))?;
-    println!("{:?}", 
+    println!(
+        "{:?}",
         filtered.select((
-            "location", 
-            "total_cases", 
-            "new_cases", 
-            "total_deaths", 
+            "location",
+            "total_cases",
+            "new_cases",
+            "total_deaths",
             "new_deaths"
         ))
     );
@@ -27,4 +28,4 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-// cargo run --example covid --quiet
\ No newline at end of file
+// cargo run --example covid --quiet
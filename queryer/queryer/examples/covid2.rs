@@ -9,7 +9,7 @@ async fn main() -> Result<()> {
 
     let _sql = format!(
         "SELECT location name, total_cases, new_cases, total_deaths, new_deaths \
-        FROM {} WHERE new_deaths >= 500 ORDER BY new_cases DESC",
+        FROM '{}' WHERE new_deaths >= 500 ORDER BY new_cases DESC",
         url
     );
 
@@ -17,4 +17,4 @@ async fn main() -> Result<()> {
     println!("{:?}", df1);
 
     Ok(())
-}
\ No newline at end of file
+}
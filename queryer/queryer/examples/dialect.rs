@@ -1,4 +1,4 @@
-use sqlparser::{ dialect::GenericDialect, parser::Parser };
+use sqlparser::{dialect::GenericDialect, parser::Parser};
 
 fn main() {
     tracing_subscriber::fmt::init();
@@ -9,9 +9,8 @@ fn main() {
                 ORDER BY a DESC, b \
                 LIMIT 50 OFFSET 10";
 
-    let ast = Parser::parse_sql(&GenericDialect::default(), sql);
+    let ast = Parser::parse_sql(&GenericDialect {}, sql);
     println!("{:#?}", ast);
-
 }
 
-// cargo run --example dialect
\ No newline at end of file
+// cargo run --example dialect
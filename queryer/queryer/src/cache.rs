@@ -0,0 +1,238 @@
+use crate::error::Result;
+use crate::fetcher::expand_glob;
+use crate::sqlite::{SqliteLoader, SQLITE_PREFIX};
+use polars::prelude::DataFrame;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+
+/// 查询结果缓存的选项
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheOptions {
+    /// 最多缓存多少个查询结果
+    pub capacity: usize,
+    /// 缓存的结果加起来最多多少行，超过时先淘汰最久没用过的结果
+    pub max_rows: usize,
+    /// 结果的有效期。远程数据源没法不重新获取就知道有没有更新，只能等结果过期
+    pub ttl: Duration,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 64,
+            max_rows: 1_000_000,
+            ttl: Duration::from_secs(60),
+        }
+    }
+}
+
+impl CacheOptions {
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn with_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = max_rows;
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+}
+
+/// 数据源的新鲜度：每个本地文件的修改时间，远程地址没有
+pub type Freshness = Vec<(String, SystemTime)>;
+
+/// 取得数据源的新鲜度，只读取文件的元数据，不读取内容
+pub async fn freshness(uri: &str) -> Result<Freshness> {
+    if uri.starts_with(SQLITE_PREFIX) {
+        let path = SqliteLoader::new(uri)?.path().to_string();
+        let modified = tokio::fs::metadata(&path).await?.modified()?;
+        let mut freshness = vec![(path.clone(), modified)];
+        // WAL 模式下，写入的数据先放在 -wal 文件里
+        let wal = format!("{}-wal", path);
+        if let Ok(metadata) = tokio::fs::metadata(&wal).await {
+            freshness.push((wal, metadata.modified()?));
+        }
+        return Ok(freshness);
+    }
+
+    let files = match expand_glob(uri)? {
+        Some(files) => files,
+        None => vec![uri.to_string()],
+    };
+
+    let mut freshness = Vec::with_capacity(files.len());
+    for file in files {
+        if let Some(path) = file.strip_prefix("file://") {
+            let modified = tokio::fs::metadata(path).await?.modified()?;
+            freshness.push((file, modified));
+        }
+    }
+    Ok(freshness)
+}
+
+struct Entry {
+    /// SQL 里写的数据源，以及它对应的地址
+    source: String,
+    uri: String,
+    freshness: Freshness,
+    data: DataFrame,
+    created: Instant,
+    used: Instant,
+}
+
+/// 查询结果缓存，以规范化之后的 SQL 为键
+pub struct ResultCache {
+    options: CacheOptions,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl fmt::Debug for ResultCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResultCache")
+            .field("options", &self.options)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl ResultCache {
+    pub fn new(options: CacheOptions) -> Self {
+        Self {
+            options,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    /// 查找缓存的结果，过期了或者数据源更新过的结果会被丢掉
+    pub fn get(&self, key: &str, freshness: &Freshness) -> Option<DataFrame> {
+        let mut entries = self.entries();
+        let entry = entries.get_mut(key)?;
+        if entry.created.elapsed() >= self.options.ttl || &entry.freshness != freshness {
+            entries.remove(key);
+            return None;
+        }
+        entry.used = Instant::now();
+        Some(entry.data.clone())
+    }
+
+    pub fn insert(
+        &self,
+        key: String,
+        source: &str,
+        uri: &str,
+        freshness: Freshness,
+        data: &DataFrame,
+    ) {
+        let rows = data.height();
+        if self.options.capacity == 0 || rows > self.options.max_rows {
+            return;
+        }
+
+        let mut entries = self.entries();
+        entries.remove(&key);
+        // 先淘汰最久没用过的结果，直到放得下新的结果
+        while entries.len() >= self.options.capacity
+            || entries.values().map(|e| e.data.height()).sum::<usize>() + rows
+                > self.options.max_rows
+        {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, e)| e.used)
+                .map(|(k, _)| k.clone());
+            match oldest {
+                Some(k) => entries.remove(&k),
+                None => break,
+            };
+        }
+
+        let now = Instant::now();
+        entries.insert(
+            key,
+            Entry {
+                source: source.to_string(),
+                uri: uri.to_string(),
+                freshness,
+                data: data.clone(),
+                created: now,
+                used: now,
+            },
+        );
+    }
+
+    /// 丢掉用到 source（注册的名字或者地址）的结果
+    pub fn invalidate(&self, source: &str) {
+        self.entries()
+            .retain(|_, e| e.source != source && e.uri != source);
+    }
+
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::*;
+
+    fn df(rows: i32) -> DataFrame {
+        DataFrame::new(vec![Series::new("a", (0..rows).collect::<Vec<_>>())]).unwrap()
+    }
+
+    #[test]
+    fn cache_evicts_and_invalidates() {
+        let cache = ResultCache::new(CacheOptions::default().with_capacity(2).with_max_rows(10));
+        let fresh = Freshness::new();
+        cache.insert("q1".into(), "t1", "file:///t1.csv", fresh.clone(), &df(3));
+        cache.insert("q2".into(), "t2", "file:///t2.csv", fresh.clone(), &df(3));
+        assert!(cache.get("q1", &fresh).is_some());
+
+        // 容量满了，淘汰最久没用过的 q2
+        cache.insert("q3".into(), "t1", "file:///t1.csv", fresh.clone(), &df(3));
+        assert!(cache.get("q2", &fresh).is_none());
+        assert_eq!(cache.len(), 2);
+
+        // 行数超过限制的结果不缓存，放不下时继续淘汰
+        cache.insert("big".into(), "t1", "file:///t1.csv", fresh.clone(), &df(11));
+        assert!(cache.get("big", &fresh).is_none());
+        cache.insert("q4".into(), "t2", "file:///t2.csv", fresh.clone(), &df(8));
+        assert_eq!(cache.len(), 1);
+
+        cache.insert("q5".into(), "t1", "file:///t1.csv", fresh.clone(), &df(1));
+        cache.invalidate("file:///t1.csv");
+        assert_eq!(cache.len(), 1);
+        cache.invalidate("t2");
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn cache_checks_ttl_and_freshness() {
+        let cache = ResultCache::new(CacheOptions::default());
+        let fresh = vec![("file:///t.csv".to_string(), SystemTime::UNIX_EPOCH)];
+        cache.insert("q".into(), "t", "file:///t.csv", fresh.clone(), &df(1));
+        assert!(cache.get("q", &fresh).is_some());
+
+        let updated = vec![("file:///t.csv".to_string(), SystemTime::now())];
+        assert!(cache.get("q", &updated).is_none());
+        assert_eq!(cache.len(), 0);
+
+        let cache = ResultCache::new(CacheOptions::default().with_ttl(Duration::ZERO));
+        cache.insert("q".into(), "t", "file:///t.csv", fresh.clone(), &df(1));
+        assert!(cache.get("q", &fresh).is_none());
+    }
+}
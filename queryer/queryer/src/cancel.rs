@@ -0,0 +1,157 @@
+use crate::error::{QueryError, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// 取消查询用的令牌，克隆出来的令牌共享同一个状态
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// 等到令牌被取消
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// 返回的值被丢弃时取消令牌
+    pub(crate) fn drop_guard(&self) -> DropGuard {
+        DropGuard(self.clone())
+    }
+
+    /// 已经取消时返回 `QueryError::Cancelled`，在执行的各个阶段之间检查
+    pub(crate) fn check(&self) -> Result<()> {
+        match self.is_cancelled() {
+            true => Err(QueryError::Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+pub(crate) struct DropGuard(CancelToken);
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// 执行查询时的选项
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    /// 整个查询（获取数据和计算）最多执行多久
+    pub timeout: Option<Duration>,
+    /// 从别的任务取消查询
+    pub cancel: CancelToken,
+    /// 最多返回多少行，多出来的行直接丢掉而不报错，这一点和 `Limits::max_result_rows` 不同。
+    /// 计算时就会截断，不会先算出全部结果
+    pub row_limit: Option<usize>,
+    /// 只读：拒绝 `COPY ... TO` 这样会写文件的语句
+    pub read_only: bool,
+    /// 只能查询注册过的数据源，不能在 SQL 里直接写文件路径或者网址
+    pub registered_sources_only: bool,
+}
+
+impl QueryOptions {
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    pub fn with_row_limit(mut self, rows: usize) -> Self {
+        self.row_limit = Some(rows);
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn with_registered_sources_only(mut self, registered_sources_only: bool) -> Self {
+        self.registered_sources_only = registered_sources_only;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Context, Params, QueryError};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn cancel_token_works() {
+        let token = CancelToken::new();
+        let waiter = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+        assert!(token.check().is_ok());
+
+        token.clone().cancel();
+        waiter.await.unwrap();
+        assert!(token.is_cancelled());
+        assert!(matches!(token.check(), Err(QueryError::Cancelled)));
+        // 已经取消之后再等待会马上返回
+        token.cancelled().await;
+    }
+
+    #[tokio::test]
+    async fn timeout_and_cancellation_work() {
+        // 接受连接但是从不响应的数据源
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("http://{}/data.csv", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+        let sql = format!("SELECT * FROM '{}'", uri);
+        let ctx = Context::new();
+
+        let options = QueryOptions::default().with_timeout(Duration::from_millis(100));
+        let err = ctx.query_with_options(&sql, Params::new(), options).await;
+        assert!(matches!(err, Err(QueryError::Timeout(_))));
+
+        let token = CancelToken::new();
+        let options = QueryOptions::default().with_cancel_token(token.clone());
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            token.cancel();
+        });
+        let err = ctx.query_with_options(&sql, Params::new(), options).await;
+        assert!(matches!(err, Err(QueryError::Cancelled)));
+    }
+}
@@ -0,0 +1,784 @@
+use crate::cache::{freshness, CacheOptions, ResultCache};
+use crate::cancel::{CancelToken, QueryOptions};
+use crate::convert::{Command, Sql};
+use crate::dialect::parse_sql;
+use crate::error::{QueryError, Result};
+use crate::fetcher::{expand_glob, retrieve_data_limited};
+use crate::json;
+use crate::limits::{check_rows, Limits};
+use crate::loader::{detect_source, Load, LoadOptions};
+use crate::params::{bind_params, Params};
+use crate::sqlite::{SqliteLoader, SQLITE_PREFIX};
+use crate::stats::{timed, timed_async, QueryStats};
+use crate::udf::{Functions, ScalarFunction};
+use crate::validate::validate_columns;
+use crate::writer::write_file;
+use crate::DataSet;
+use polars::prelude::*;
+use sqlparser::tokenizer::Span;
+use std::collections::HashMap;
+use tracing::{field, info, info_span};
+
+/// 注册过的数据源：地址加上加载选项
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub uri: String,
+    pub options: LoadOptions,
+}
+
+/// 查询上下文，保存注册过的数据源
+#[derive(Debug, Default)]
+pub struct Context {
+    tables: HashMap<String, Table>,
+    functions: Functions,
+    cache: Option<ResultCache>,
+    limits: Limits,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 以 name 注册一个数据源，之后可以在 SQL 里用 `FROM name` 引用
+    pub fn register_source(
+        &mut self,
+        name: impl Into<String>,
+        uri: impl Into<String>,
+        options: LoadOptions,
+    ) {
+        let table = Table {
+            uri: uri.into(),
+            options,
+        };
+        let name = name.into();
+        self.invalidate_source(&name);
+        self.tables.insert(name, table);
+    }
+
+    /// 取消注册一个数据源
+    pub fn deregister_source(&mut self, name: &str) -> Option<Table> {
+        self.invalidate_source(name);
+        self.tables.remove(name)
+    }
+
+    /// 注册一个标量函数，之后可以在 SQL 的投影和 WHERE 里用 `name(arg, ...)` 调用，函数名不区分大小写。
+    /// 参数会先转换成 args 里声明的类型再传给 f，f 返回的结果会转换成 returns。
+    /// 同名的内置函数优先
+    pub fn register_function<F>(&mut self, name: &str, args: Vec<DataType>, returns: DataType, f: F)
+    where
+        F: Fn(&[Series]) -> Result<Series> + Send + Sync + 'static,
+    {
+        self.invalidate_cache();
+        self.functions
+            .insert(name, ScalarFunction::new(args, returns, f));
+    }
+
+    /// 取消注册一个标量函数
+    pub fn deregister_function(&mut self, name: &str) -> Option<ScalarFunction> {
+        self.invalidate_cache();
+        self.functions.remove(name)
+    }
+
+    /// 打开查询结果缓存。同样的查询（忽略空白和关键字大小写，包括绑定的参数）在数据源没有更新、
+    /// 结果没有过期时直接返回缓存的结果，不再获取数据和计算。
+    /// 本地文件通过修改时间判断是否更新，远程数据源只能依赖 ttl
+    pub fn enable_cache(&mut self, options: CacheOptions) {
+        self.cache = Some(ResultCache::new(options));
+    }
+
+    pub fn disable_cache(&mut self) {
+        self.cache = None;
+    }
+
+    /// 清空所有缓存的结果
+    pub fn invalidate_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+
+    /// 丢掉用到 source（注册的名字或者地址）的缓存结果
+    pub fn invalidate_source(&self, source: &str) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(source);
+        }
+    }
+
+    /// 设置资源上限，缓存的结果会被清空
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.invalidate_cache();
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.get(name)
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = (&str, &Table)> {
+        self.tables.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// 读取数据源：先查注册过的名字，找不到就把 source 当成地址，使用默认选项
+    pub async fn read_source(&self, source: &str) -> Result<DataSet> {
+        self.load_table(source, &mut QueryStats::default()).await
+    }
+
+    async fn load_table(&self, source: &str, stats: &mut QueryStats) -> Result<DataSet> {
+        let (uri, mut options) = match self.tables.get(source) {
+            Some(table) => (table.uri.as_str(), table.options.clone()),
+            None => (source, LoadOptions::default()),
+        };
+        // 数据源自己声明的行数上限和全局的上限，取更小的那个
+        options.max_rows = match (options.max_rows, self.limits.max_rows_loaded) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        load_source(uri, &options, self.limits.max_fetch_bytes, stats).await
+    }
+
+    pub async fn query<T: AsRef<str>>(&self, sql: T) -> Result<DataSet> {
+        self.query_with_params(sql, Params::default()).await
+    }
+
+    /// 执行带占位符（`$1`、`?`、`:name`）的查询，params 里的值会绑定到对应的占位符上
+    pub async fn query_with_params<T: AsRef<str>>(
+        &self,
+        sql: T,
+        params: impl Into<Params>,
+    ) -> Result<DataSet> {
+        self.query_with_options(sql, params, QueryOptions::default())
+            .await
+    }
+
+    /// 执行查询，超时或者被取消时返回 `QueryError::Timeout` / `QueryError::Cancelled`。
+    /// polars 的计算没法中途打断，只会在各个阶段之间停下，但查询会马上返回
+    pub async fn query_with_options<T: AsRef<str>>(
+        &self,
+        sql: T,
+        params: impl Into<Params>,
+        options: QueryOptions,
+    ) -> Result<DataSet> {
+        let (ds, _) = self.query_with_stats(sql, params, options).await?;
+        Ok(ds)
+    }
+
+    /// 和 [`Context::query_with_options`] 一样，同时返回各个阶段的耗时和数据量
+    pub async fn query_with_stats<T: AsRef<str>>(
+        &self,
+        sql: T,
+        params: impl Into<Params>,
+        options: QueryOptions,
+    ) -> Result<(DataSet, QueryStats)> {
+        // 这次查询自己的令牌，查询结束（包括 future 被丢弃）时取消它，让还在后台执行的计算尽早停下
+        let token = CancelToken::new();
+        let _guard = token.drop_guard();
+        let timeout = async {
+            match options.timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            result = self.run(sql.as_ref(), params.into(), &options, &token) => result,
+            _ = options.cancel.cancelled() => Err(QueryError::Cancelled),
+            _ = timeout => Err(QueryError::Timeout(options.timeout.unwrap_or_default())),
+        }
+    }
+
+    async fn run(
+        &self,
+        sql: &str,
+        params: Params,
+        options: &QueryOptions,
+        token: &CancelToken,
+    ) -> Result<(DataSet, QueryStats)> {
+        let mut stats = QueryStats::default();
+        let span = info_span!("parse", elapsed_ms = field::Empty);
+        let (ast, elapsed) = timed(&span, || {
+            let mut ast = parse_sql(sql)?;
+            match ast.as_slice() {
+                [_] => {}
+                [_, other, ..] => {
+                    return Err(QueryError::unsupported(
+                        "Only support single sql at the moment!",
+                        other,
+                    ))
+                }
+                [] => {
+                    return Err(QueryError::Unsupported {
+                        message: "Only support single sql at the moment!".into(),
+                        fragment: String::new(),
+                        span: Span::empty(),
+                    })
+                }
+            }
+            bind_params(&mut ast[0], &params)?;
+            Ok(ast)
+        });
+        stats.parse = elapsed;
+        let ast = ast?;
+
+        let span = info_span!("plan", elapsed_ms = field::Empty);
+        let (command, elapsed) = timed(&span, || Command::new(&ast[0], &self.functions));
+        stats.plan = elapsed;
+        let command = command?;
+        self.check_allowed(&command, options)?;
+
+        let ds = match command {
+            Command::Query(sql) => match &self.cache {
+                Some(cache) if !sql.volatile => {
+                    // 截断的结果和完整的结果不能混用
+                    let key = match options.row_limit {
+                        Some(rows) => format!("{} /* row_limit {} */", ast[0], rows),
+                        None => ast[0].to_string(),
+                    };
+                    self.execute_cached(cache, key, sql, options.row_limit, token, &mut stats)
+                        .await?
+                }
+                _ => {
+                    self.execute(sql, options.row_limit, token, &mut stats)
+                        .await?
+                }
+            },
+            Command::Describe(source) => {
+                info!("describing source: {}", source);
+                self.load_table(source, &mut stats).await?.describe()?
+            }
+            Command::Copy {
+                query,
+                target,
+                format,
+            } => {
+                let ds = self.execute(query, None, token, &mut stats).await?;
+                info!("writing {} rows to {}", ds.height(), target);
+                stats.serialize = write_file(&ds, target, format).await?;
+                let df = DataFrame::new(vec![Series::new("rows", &[ds.height() as i64])])?;
+                DataSet(df)
+            }
+        };
+        stats.rows_returned = ds.height();
+        Ok((ds, stats))
+    }
+
+    /// 检查语句有没有用到 options 禁止的功能
+    fn check_allowed(&self, command: &Command, options: &QueryOptions) -> Result<()> {
+        let source = match command {
+            Command::Copy { .. } if options.read_only => {
+                return Err(QueryError::NotAllowed(
+                    "COPY is not allowed in read-only mode".into(),
+                ))
+            }
+            Command::Copy { query, .. } => query.source,
+            Command::Query(sql) => sql.source,
+            Command::Describe(source) => source,
+        };
+        if options.registered_sources_only && !self.tables.contains_key(source) {
+            return Err(QueryError::NotAllowed(format!(
+                "Source `{}` is not registered",
+                source
+            )));
+        }
+        Ok(())
+    }
+
+    /// 先查缓存，没有命中时执行查询并缓存结果
+    async fn execute_cached(
+        &self,
+        cache: &ResultCache,
+        key: String,
+        sql: Sql<'_>,
+        row_limit: Option<usize>,
+        token: &CancelToken,
+        stats: &mut QueryStats,
+    ) -> Result<DataSet> {
+        let source = sql.source;
+        let uri = self.tables.get(source).map_or(source, |t| t.uri.as_str());
+        // 拿不到新鲜度（比如文件不存在）时不使用缓存，让查询报出具体的错误
+        let freshness = match freshness(uri).await {
+            Ok(freshness) => freshness,
+            Err(_) => return self.execute(sql, row_limit, token, stats).await,
+        };
+
+        if let Some(df) = cache.get(&key, &freshness) {
+            info!("using cached result for: {}", key);
+            stats.cached = true;
+            return Ok(DataSet(df));
+        }
+
+        let ds = self.execute(sql, row_limit, token, stats).await?;
+        cache.insert(key, source, uri, freshness, &ds);
+        Ok(ds)
+    }
+
+    /// 获取数据之后，在后台线程里执行 polars 的计算，每个阶段之前检查是否已经取消。
+    /// row_limit 是 `QueryOptions::row_limit`
+    async fn execute(
+        &self,
+        sql: Sql<'_>,
+        row_limit: Option<usize>,
+        token: &CancelToken,
+        stats: &mut QueryStats,
+    ) -> Result<DataSet> {
+        let Sql {
+            source,
+            condition,
+            selection,
+            offset,
+            limit,
+            sample,
+            reshapes,
+            unnest,
+            volatile: _,
+            order_by,
+            columns,
+        } = sql;
+
+        let mut ds = self.load_table(source, stats).await?;
+        let token = token.clone();
+        let max_result_rows = self.limits.max_result_rows;
+        let span = info_span!("execute", rows = field::Empty, elapsed_ms = field::Empty);
+        let task = tokio::task::spawn_blocking(move || -> Result<DataSet> {
+            token.check()?;
+            if let Some(sample) = sample {
+                ds = DataSet(sample.apply(&ds)?);
+            }
+            for reshape in &reshapes {
+                token.check()?;
+                ds = DataSet(reshape.apply(&ds)?);
+            }
+            validate_columns(&columns, &ds)?;
+            let mut source_columns: Vec<String> = ds
+                .get_column_names()
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+
+            let mut filtered = match condition {
+                Some(expr) => ds.0.lazy().filter(expr),
+                None => ds.0.lazy(),
+            };
+
+            // UNNEST 的列先算出来再展开成多行，其它列的值跟着重复
+            if let Some((name, expr)) = unnest {
+                token.check()?;
+                let df = filtered.with_column(expr.alias(&name)).collect()?;
+                filtered = json::explode(&df, &name)?.lazy();
+                if !source_columns.contains(&name) {
+                    source_columns.push(name);
+                }
+            }
+
+            // 排序用的表达式先算成临时列，排好序之后再去掉
+            let mut by = Vec::with_capacity(order_by.len());
+            let mut reverse = Vec::with_capacity(order_by.len());
+            let mut temporary = false;
+            for (i, (expr, desc)) in order_by.into_iter().enumerate() {
+                let name = match expr {
+                    Expr::Column(name) => name.to_string(),
+                    expr => {
+                        let name = format!("__order_by_{}", i);
+                        filtered = filtered.with_column(expr.alias(&name));
+                        temporary = true;
+                        name
+                    }
+                };
+                by.push(col(&name));
+                reverse.push(desc);
+            }
+            filtered = filtered.sort_by_exprs(by, reverse);
+            if temporary {
+                filtered = filtered.select(
+                    source_columns
+                        .iter()
+                        .map(|name| col(name))
+                        .collect::<Vec<_>>(),
+                );
+            }
+
+            if offset.is_some() || limit.is_some() {
+                filtered = filtered.slice(offset.unwrap_or(0), limit.unwrap_or(usize::MAX));
+            }
+            if let Some(rows) = row_limit {
+                filtered = filtered.slice(0, rows);
+            }
+            // 多取一行，用来判断结果是不是超过了上限，不用把所有结果都算出来
+            if let Some(max) = max_result_rows {
+                filtered = filtered.slice(0, max + 1);
+            }
+
+            token.check()?;
+            let df = filtered.select(selection).collect()?;
+            check_rows("query result", df.height(), max_result_rows)?;
+            Ok(DataSet(df))
+        });
+        let (result, elapsed) = timed_async(&span, async {
+            let ds = task.await.map_err(QueryError::execution)??;
+            span.record("rows", &ds.height());
+            Ok(ds)
+        })
+        .await;
+        stats.execute = elapsed;
+        result
+    }
+}
+
+/// 从通配符展开的数据源里读出来的数据，会多一列记录来自哪个文件
+pub const SOURCE_FILE_COLUMN: &str = "_source_file";
+
+/// 用给定的加载选项读取一个数据源地址
+pub async fn read_source(uri: &str, options: &LoadOptions) -> Result<DataSet> {
+    load_source(uri, options, None, &mut QueryStats::default()).await
+}
+
+/// 读取数据源，每个文件或者地址最多读取 max_bytes 字节
+async fn load_source(
+    uri: &str,
+    options: &LoadOptions,
+    max_bytes: Option<u64>,
+    stats: &mut QueryStats,
+) -> Result<DataSet> {
+    if uri.starts_with(SQLITE_PREFIX) {
+        // SQLite 边读边转换，整个算作加载
+        let loader = SqliteLoader::new(uri)?;
+        let options = options.clone();
+        let span = info_span!("load", uri, rows = field::Empty, elapsed_ms = field::Empty);
+        let (result, elapsed) = timed_async(&span, async {
+            let ds = tokio::task::spawn_blocking(move || loader.load(&options))
+                .await
+                .map_err(QueryError::execution)??;
+            span.record("rows", &ds.height());
+            Ok(ds)
+        })
+        .await;
+        stats.load += elapsed;
+        stats.rows_loaded += result.as_ref().map_or(0, |ds| ds.height());
+        return result;
+    }
+
+    match expand_glob(uri)? {
+        Some(files) => read_files(uri, &files, options, max_bytes, stats).await,
+        None => {
+            let data = fetch_data(uri, max_bytes, stats).await?;
+            load_data(uri, data, options, stats)
+        }
+    }
+}
+
+/// 获取一个文件或者地址的内容，记录耗时和字节数
+async fn fetch_data(uri: &str, max_bytes: Option<u64>, stats: &mut QueryStats) -> Result<Vec<u8>> {
+    info!("retrieving data from source: {}", uri);
+    let span = info_span!(
+        "fetch",
+        uri,
+        bytes = field::Empty,
+        elapsed_ms = field::Empty
+    );
+    let (result, elapsed) = timed_async(&span, async {
+        let data = retrieve_data_limited(uri, max_bytes).await?;
+        span.record("bytes", &(data.len() as u64));
+        Ok(data)
+    })
+    .await;
+    stats.fetch += elapsed;
+    stats.bytes_fetched += result.as_ref().map_or(0, |data| data.len() as u64);
+    result
+}
+
+/// 把获取到的内容解析成 DataSet，记录耗时和行数
+fn load_data(
+    uri: &str,
+    data: Vec<u8>,
+    options: &LoadOptions,
+    stats: &mut QueryStats,
+) -> Result<DataSet> {
+    let span = info_span!("load", uri, rows = field::Empty, elapsed_ms = field::Empty);
+    let (result, elapsed) = timed(&span, || {
+        let ds = detect_source(uri, data).load(options)?;
+        span.record("rows", &ds.height());
+        Ok(ds)
+    });
+    stats.load += elapsed;
+    stats.rows_loaded += result.as_ref().map_or(0, |ds| ds.height());
+    result
+}
+
+/// 逐个读取文件，加上 `_source_file` 列之后合并成一个 DataSet。
+/// 字节数和行数的上限针对所有文件的总和，每个文件只能用前面的文件剩下的额度
+async fn read_files(
+    source: &str,
+    files: &[String],
+    options: &LoadOptions,
+    max_bytes: Option<u64>,
+    stats: &mut QueryStats,
+) -> Result<DataSet> {
+    let exceeded = |e, limit: String| match e {
+        QueryError::LimitExceeded(_) => {
+            QueryError::LimitExceeded(format!("files matching {} {}", source, limit))
+        }
+        e => e,
+    };
+    let mut bytes = 0;
+    let mut combined: Option<DataFrame> = None;
+    for file in files {
+        let remaining = max_bytes.map(|max| max.saturating_sub(bytes));
+        let data = fetch_data(file, remaining, stats).await.map_err(|e| {
+            let max = max_bytes.unwrap_or_default();
+            exceeded(
+                e,
+                format!("are larger than the limit of {} bytes in total", max),
+            )
+        })?;
+        bytes += data.len() as u64;
+
+        let rows = combined.as_ref().map_or(0, |df| df.height());
+        let mut file_options = options.clone();
+        file_options.max_rows = options.max_rows.map(|max| max.saturating_sub(rows));
+        let mut df = load_data(file, data, &file_options, stats)
+            .map_err(|e| {
+                let max = options.max_rows.unwrap_or_default();
+                exceeded(e, format!("have more than {} rows in total", max))
+            })?
+            .0;
+        let path = file.strip_prefix("file://").unwrap_or(file);
+        df.with_column(Series::new(SOURCE_FILE_COLUMN, vec![path; df.height()]))?;
+
+        combined = Some(match combined {
+            None => df,
+            Some(acc) => acc.vstack(&df).map_err(|e| {
+                QueryError::Load(format!(
+                    "cannot combine {} with previous files: {} (declare a schema to make column types consistent)",
+                    path,
+                    e
+                ))
+            })?,
+        });
+    }
+
+    combined
+        .map(DataSet)
+        .ok_or_else(|| QueryError::Load("No file to read".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{numbers_context, numbers_csv, TempFile};
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn registered_source_uses_declared_schema() {
+        let file = numbers_csv();
+        let mut ctx = Context::new();
+        let options = LoadOptions::default().with_schema(vec![("value", DataType::Float64)]);
+        ctx.register_source("numbers", file.uri(), options);
+
+        let ds = ctx
+            .query("SELECT name, value FROM numbers WHERE value > 1 AND value < 2")
+            .await
+            .unwrap();
+        assert_eq!(ds.height(), 1);
+        assert_eq!(ds.column("value").unwrap().dtype(), &DataType::Float64);
+    }
+
+    #[tokio::test]
+    async fn describe_works() {
+        let (ctx, _file) = numbers_context();
+
+        for sql in ["DESCRIBE numbers", "SHOW COLUMNS FROM numbers"] {
+            let ds = ctx.query(sql).await.unwrap();
+            assert_eq!(ds.shape(), (2, 4));
+            assert_eq!(ds.column("column").unwrap().get(1), AnyValue::Utf8("value"));
+            assert_eq!(
+                ds.column("dtype").unwrap().get(1),
+                AnyValue::Utf8("Float64")
+            );
+            assert_eq!(ds.column("null_count").unwrap().get(1), AnyValue::UInt32(0));
+            assert_eq!(ds.column("sample").unwrap().get(0), AnyValue::Utf8("row0"));
+        }
+    }
+
+    #[tokio::test]
+    async fn glob_source_works() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, value) in [("2020-12", 0), ("2021-01", 1), ("2021-02", 2)] {
+            let content = format!("name,value\n{},{}\n{},{}\n", name, value, name, value * 10);
+            std::fs::write(dir.path().join(format!("{}.csv", name)), content).unwrap();
+        }
+
+        let sql = format!(
+            "SELECT name, value, _source_file FROM 'file://{}/2021-*.csv' WHERE value > 1",
+            dir.path().display()
+        );
+        let ds = Context::new().query(sql).await.unwrap();
+        assert_eq!(ds.height(), 3);
+        let file = dir.path().join("2021-02.csv").display().to_string();
+        assert_eq!(
+            ds.column(SOURCE_FILE_COLUMN).unwrap().get(2),
+            AnyValue::Utf8(&file)
+        );
+
+        // 上限针对匹配到的所有文件的总和，而不是每个文件
+        let sql = format!("SELECT * FROM 'file://{}/2021-*.csv'", dir.path().display());
+        let size = std::fs::metadata(&file).unwrap().len();
+        let mut ctx = Context::new();
+        for limits in [
+            Limits::default().with_max_rows_loaded(3),
+            Limits::default().with_max_fetch_bytes(size + 1),
+        ] {
+            ctx.set_limits(limits);
+            let err = ctx.query(&sql).await.unwrap_err();
+            assert!(
+                matches!(&err, QueryError::LimitExceeded(e) if e.contains("in total")),
+                "{:?}",
+                err
+            );
+        }
+        ctx.set_limits(
+            Limits::default()
+                .with_max_rows_loaded(4)
+                .with_max_fetch_bytes(size * 2),
+        );
+        assert_eq!(ctx.query(&sql).await.unwrap().height(), 4);
+    }
+
+    #[tokio::test]
+    async fn errors_are_typed() {
+        let ctx = Context::new();
+        let err = ctx.query("SELECT a FROM").await.unwrap_err();
+        assert!(matches!(err, QueryError::Parse(_)), "{:?}", err);
+
+        let err = ctx
+            .query("SELECT a FROM 'file:///not/exist.csv'")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, QueryError::Fetch { uri, .. } if uri == "file:///not/exist.csv"),
+            "{:?}",
+            err
+        );
+
+        let err = ctx
+            .query("SELECT a FROM t; SELECT b FROM t")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, QueryError::Unsupported { .. }), "{:?}", err);
+
+        let options = QueryOptions::default()
+            .with_read_only(true)
+            .with_registered_sources_only(true);
+        for sql in [
+            "SELECT a FROM 'file:///etc/hosts'",
+            "COPY (SELECT a FROM t) TO 'file:///tmp/out.csv'",
+        ] {
+            let err = ctx
+                .query_with_options(sql, Params::new(), options.clone())
+                .await
+                .unwrap_err();
+            assert!(matches!(err, QueryError::NotAllowed(_)), "{:?}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn case_when_works() {
+        let (ctx, _file) = numbers_context();
+
+        let sql = "SELECT name, \
+                   CASE WHEN value >= 15 THEN 'high' WHEN value >= 5 THEN 'mid' ELSE 'low' END AS level, \
+                   CASE name WHEN 'row3' THEN 1 END AS flag \
+                   FROM numbers \
+                   WHERE CASE WHEN value < 2 THEN true ELSE value > 17 END \
+                   ORDER BY CASE WHEN value < 2 THEN 0 ELSE 1 END DESC, name";
+        let ds = ctx.query(sql).await.unwrap();
+        let names: Vec<_> = ds
+            .column("name")
+            .unwrap()
+            .utf8()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(
+            names,
+            [
+                Some("row18"),
+                Some("row19"),
+                Some("last"),
+                Some("row0"),
+                Some("row1")
+            ]
+        );
+        let level = ds.column("level").unwrap();
+        assert_eq!(level.get(0), AnyValue::Utf8("high"));
+        assert_eq!(level.get(2), AnyValue::Utf8("low"));
+        assert_eq!(ds.column("flag").unwrap().null_count(), 5);
+    }
+
+    #[tokio::test]
+    async fn result_cache_works() {
+        let file = TempFile::new(".csv", "name,value\na,1\nb,2\n");
+        let mut ctx = Context::new();
+        ctx.enable_cache(CacheOptions::default());
+        ctx.register_source("t", file.uri(), LoadOptions::default());
+        let cached = |ctx: &Context| ctx.cache.as_ref().unwrap().len();
+
+        let ds = ctx
+            .query("SELECT name FROM t WHERE value > 1")
+            .await
+            .unwrap();
+        assert_eq!(ds.height(), 1);
+        assert_eq!(cached(&ctx), 1);
+
+        // 空白和关键字大小写不同也是同一个查询
+        let ds = ctx
+            .query("select name   from t where value > 1")
+            .await
+            .unwrap();
+        assert_eq!(ds.height(), 1);
+        assert_eq!(cached(&ctx), 1);
+
+        // 文件更新之后重新执行
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(file.path())
+            .unwrap();
+        writeln!(file, "c,3").unwrap();
+        file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(10))
+            .unwrap();
+        let ds = ctx
+            .query("SELECT name FROM t WHERE value > 1")
+            .await
+            .unwrap();
+        assert_eq!(ds.height(), 2);
+
+        // NOW() 每次结果都不一样，不缓存
+        ctx.query("SELECT NOW() AS now FROM t").await.unwrap();
+        assert_eq!(cached(&ctx), 1);
+
+        ctx.invalidate_source("t");
+        assert_eq!(cached(&ctx), 0);
+    }
+
+    #[tokio::test]
+    async fn row_limit_truncates_result() {
+        let (mut ctx, _file) = numbers_context();
+        ctx.enable_cache(CacheOptions::default());
+
+        let sql = "SELECT name FROM numbers WHERE value >= 10 ORDER BY value DESC";
+        let options = QueryOptions::default().with_row_limit(3);
+        let ds = ctx
+            .query_with_options(sql, Params::new(), options)
+            .await
+            .unwrap();
+        assert_eq!(ds.height(), 3);
+        assert_eq!(ds.column("name").unwrap().get(0), AnyValue::Utf8("row19"));
+
+        // 截断的结果不会被当成完整的结果
+        let ds = ctx.query(sql).await.unwrap();
+        assert_eq!(ds.height(), 10);
+    }
+}
@@ -1,115 +1,847 @@
-use anyhow::{anyhow, Result};
+use crate::error::{QueryError, Result};
+use crate::json;
+use crate::regexp;
+use crate::reshape::{PivotAggregate, PivotColumn, Reshape};
+use crate::sample::{Sample, SampleSize};
+use crate::temporal::{self, DateField, Interval};
+use crate::udf::Functions;
+use crate::writer::Format;
 use polars::prelude::*;
+use regex::Regex;
 use sqlparser::ast::{
-    BinaryOperator as SqlBinaryOperator, Expr as SqlExpr, Offset as SqlOffset, OrderByExpr, Select,
-    SelectItem, SetExpr, Statement, TableFactor, TableWithJoins, Value as SqlValue,
+    visit_expressions, BinaryOperator as SqlBinaryOperator, CaseWhen, CastKind, CopyOption,
+    CopySource, CopyTarget, DataType as SqlDataType, DateTimeField, Expr as SqlExpr,
+    Function as SqlFunction, FunctionArg, FunctionArgExpr, FunctionArguments, Ident, LimitClause,
+    NullInclusion, ObjectName, OrderByExpr, OrderByKind, OrderBySort, PivotValueSource, Query,
+    Select, SelectItem, SelectItemQualifiedWildcardKind, SetExpr, ShowStatementIn, Spanned,
+    Statement, TableFactor, TableSampleKind, TableSampleUnit, TableWithJoins, TypedString,
+    UnaryOperator, Value as SqlValue, ValueWithSpan, Visit,
 };
+use sqlparser::tokenizer::Span;
 use std::convert::{TryFrom, TryInto};
+use std::ops::ControlFlow;
+
+/// 解析出来的语句
+pub enum Command<'a> {
+    /// SELECT ... FROM ... WHERE ...
+    Query(Sql<'a>),
+    /// DESCRIBE source / SHOW COLUMNS FROM source
+    Describe(&'a str),
+    /// COPY (SELECT ...) TO 'file:///out.parquet' (FORMAT parquet)
+    Copy {
+        query: Sql<'a>,
+        target: &'a str,
+        format: Option<Format>,
+    },
+}
 
 /// 解析出来的 SQL
 pub struct Sql<'a> {
     pub(crate) selection: Vec<Expr>,
     pub(crate) condition: Option<Expr>,
     pub(crate) source: &'a str,
-    pub(crate) order_by: Vec<(String, bool)>,
+    pub(crate) order_by: Vec<(Expr, bool)>,
     pub(crate) offset: Option<i64>,
     pub(crate) limit: Option<usize>,
+    pub(crate) sample: Option<Sample>,
+    /// FROM 里的 PIVOT / UNPIVOT，按从里到外的顺序
+    pub(crate) reshapes: Vec<Reshape>,
+    /// SELECT 里的 UNNEST(expr)：先算出列名对应的列，再展开成多行
+    pub(crate) unnest: Option<(String, Expr)>,
+    /// 每次执行结果都可能不同（用到 NOW() 或者没有 seed 的抽样），不能缓存
+    pub(crate) volatile: bool,
+    /// SQL 里引用到的列，执行前用来校验
+    pub(crate) columns: Vec<Ident>,
 }
 
 // 因为 Rust trait 的孤儿规则，我们如果要想对已有的类型实现已有的 trait，
 // 需要简单包装一下
 
-pub struct Expression(pub(crate) Box<SqlExpr>);
-pub struct Operation(pub(crate) SqlBinaryOperator);
-pub struct Projection<'a>(pub(crate) &'a SelectItem);
+pub struct Expression<'f>(pub(crate) Box<SqlExpr>, pub(crate) &'f Functions);
+pub struct Operation(pub(crate) SqlBinaryOperator, pub(crate) Span);
+pub struct Projection<'a>(pub(crate) &'a SelectItem, pub(crate) &'a Functions);
 pub struct Source<'a>(pub(crate) &'a [TableWithJoins]);
-pub struct Order<'a>(pub(crate) &'a OrderByExpr);
-pub struct Offset<'a>(pub(crate) &'a SqlOffset);
+pub struct Order<'a>(pub(crate) &'a OrderByExpr, pub(crate) &'a Functions);
+pub struct Offset<'a>(pub(crate) &'a SqlExpr);
 pub struct Limit<'a>(pub(crate) &'a SqlExpr);
-pub struct Value(pub(crate) SqlValue);
+pub struct Value(pub(crate) ValueWithSpan);
+pub struct Function<'f>(pub(crate) SqlFunction, pub(crate) &'f Functions);
+
+/// 不使用自定义函数，把 Statement 转换成我们支持的语句
+impl<'a> TryFrom<&'a Statement> for Command<'a> {
+    type Error = QueryError;
+
+    fn try_from(sql: &'a Statement) -> Result<Self, Self::Error> {
+        Command::new(sql, &Functions::default())
+    }
+}
+
+impl<'a> Command<'a> {
+    /// 把 SqlParser 解析出来的 Statement 转换成我们支持的语句，functions 是 SQL 里可以调用的自定义函数
+    pub fn new(sql: &'a Statement, functions: &Functions) -> Result<Self> {
+        match sql {
+            Statement::Query(q) => Ok(Command::Query(Sql::from_query(q, functions)?)),
+            Statement::ExplainTable { table_name, .. } => {
+                Ok(Command::Describe(table_name_to_str(table_name)?))
+            }
+            Statement::ShowColumns { show_options, .. } => match &show_options.show_in {
+                Some(ShowStatementIn {
+                    parent_name: Some(name),
+                    ..
+                }) => Ok(Command::Describe(table_name_to_str(name)?)),
+                _ => Err(QueryError::unsupported(
+                    "SHOW COLUMNS requires FROM <source>",
+                    sql,
+                )),
+            },
+            Statement::Copy {
+                source: CopySource::Query(q),
+                to: true,
+                target: CopyTarget::File { filename },
+                options,
+                ..
+            } => {
+                let mut format = None;
+                for option in options {
+                    match option {
+                        CopyOption::Format(f) => {
+                            let parsed = f.value.parse().map_err(|_| QueryError::Unsupported {
+                                message: "COPY format is not supported".into(),
+                                fragment: f.to_string(),
+                                span: f.span,
+                            })?;
+                            format = Some(parsed)
+                        }
+                        _ => {
+                            return Err(QueryError::unsupported(
+                                "COPY option is not supported",
+                                sql,
+                            ))
+                        }
+                    }
+                }
+                Ok(Command::Copy {
+                    query: Sql::from_query(q, functions)?,
+                    target: filename,
+                    format,
+                })
+            }
+            Statement::Copy { .. } => Err(QueryError::unsupported(
+                "We only support COPY (SELECT ...) TO '<file>' at the moment",
+                sql,
+            )),
+            _ => Err(QueryError::unsupported(
+                "We only support Query, DESCRIBE and COPY at the moment",
+                sql,
+            )),
+        }
+    }
+}
 
 /// 把 SqlParser 解析出来的 Statement 转换成我们需要的结构
 impl<'a> TryFrom<&'a Statement> for Sql<'a> {
-    type Error = anyhow::Error;
+    type Error = QueryError;
 
     fn try_from(sql: &'a Statement) -> Result<Self, Self::Error> {
         match sql {
             // 目前我们只关心 query (select ... from ... where ...)
-            Statement::Query(q) => {
-                let offset = q.offset.as_ref();
-                let limit = q.limit.as_ref();
-                let orders = &q.order_by;
-                let Select {
-                    from: table_with_joins,
-                    selection: where_clause,
-                    projection,
-
-                    group_by: _,
-                    ..
-                } = match &q.body {
-                    SetExpr::Select(statement) => statement.as_ref(),
-                    _ => return Err(anyhow!("We only support Select Query at the moment")),
-                };
+            Statement::Query(q) => Sql::from_query(q, &Functions::default()),
+            _ => Err(QueryError::unsupported(
+                "We only support Query at the moment",
+                sql,
+            )),
+        }
+    }
+}
 
-                let source = Source(table_with_joins).try_into()?;
+impl<'a> Sql<'a> {
+    pub fn from_query(q: &'a Query, functions: &Functions) -> Result<Self> {
+        let (limit, offset) = match &q.limit_clause {
+            Some(LimitClause::LimitOffset { limit, offset, .. }) => {
+                (limit.as_ref(), offset.as_ref().map(|o| &o.value))
+            }
+            Some(LimitClause::OffsetCommaLimit { offset, limit }) => (Some(limit), Some(offset)),
+            None => (None, None),
+        };
+        let orders: &[OrderByExpr] = match q.order_by.as_ref().map(|o| &o.kind) {
+            Some(OrderByKind::Expressions(exprs)) => exprs,
+            Some(OrderByKind::All(_)) => {
+                return Err(QueryError::unsupported(
+                    "ORDER BY ALL is not supported",
+                    q.order_by.as_ref().unwrap(),
+                ))
+            }
+            None => &[],
+        };
+        let Select {
+            from: table_with_joins,
+            selection: where_clause,
+            projection,
 
-                let condition = match where_clause {
-                    Some(expr) => Some(Expression(Box::new(expr.to_owned())).try_into()?),
-                    None => None,
-                };
+            group_by: _,
+            ..
+        } = match q.body.as_ref() {
+            SetExpr::Select(statement) => statement.as_ref(),
+            body => {
+                return Err(QueryError::unsupported(
+                    "We only support Select Query at the moment",
+                    body,
+                ))
+            }
+        };
 
-                let mut selection = Vec::with_capacity(8);
-                for p in projection {
-                    let expr = Projection(p).try_into()?;
-                    selection.push(expr);
-                }
+        let source = Source(table_with_joins).try_into()?;
+        let relation = table_with_joins.first().map(|t| &t.relation);
+        let sample = match relation.map(base_table) {
+            Some(
+                relation @ TableFactor::Table {
+                    sample: Some(kind), ..
+                },
+            ) => Some(table_sample(kind, relation.span())?),
+            _ => None,
+        };
+        let mut reshapes = Vec::new();
+        if let Some(relation) = relation {
+            collect_reshapes(relation, &mut reshapes)?;
+        }
+        let volatile = matches!(sample, Some(Sample { seed: None, .. })) || calls_now(q);
 
-                let mut order_by = Vec::new();
-                for expr in orders {
-                    order_by.push(Order(expr).try_into()?);
+        let condition = match where_clause {
+            Some(expr) => Some(Expression(Box::new(expr.to_owned()), functions).try_into()?),
+            None => None,
+        };
+
+        let mut selection = Vec::with_capacity(8);
+        let mut unnest = None;
+        for p in projection {
+            match unnest_item(p) {
+                Some(_) if unnest.is_some() => {
+                    return Err(QueryError::unsupported(
+                        "We only support one UNNEST in SELECT at the moment",
+                        p,
+                    ))
+                }
+                Some((arg, name)) => {
+                    let expr = Expression(Box::new(arg.clone()), functions).try_into()?;
+                    selection.push(col(&name));
+                    unnest = Some((name, json::unnest(expr)));
                 }
+                None => selection.push(Projection(p, functions).try_into()?),
+            }
+        }
 
-                let offset = offset.map(|v| Offset(v).into());
-                let limit = limit.map(|v| Limit(v).into());
+        let mut order_by = Vec::new();
+        for expr in orders {
+            order_by.push(Order(expr, functions).try_into()?);
+        }
 
-                Ok(Sql {
-                    selection,
-                    condition,
-                    source,
-                    order_by,
-                    offset,
-                    limit,
-                })
+        let mut columns = Vec::new();
+        column_refs(projection, &mut columns);
+        column_refs(where_clause, &mut columns);
+        // ORDER BY 可以引用 UNNEST 展开出来的列
+        let mut order_columns = Vec::new();
+        for expr in orders {
+            column_refs(expr, &mut order_columns);
+        }
+        order_columns.retain(|id| !matches!(&unnest, Some((name, _)) if name == &id.value));
+        columns.extend(order_columns);
+
+        let offset = offset.map(|v| Offset(v).try_into()).transpose()?;
+        let mut limit = limit.map(|v| Limit(v).try_into()).transpose()?;
+
+        // FETCH FIRST n ROWS ONLY 等价于 LIMIT n，不写 n 时是 1 行
+        if let Some(fetch) = &q.fetch {
+            if fetch.with_ties || fetch.percent || limit.is_some() {
+                return Err(QueryError::unsupported(
+                    "We only support FETCH FIRST n ROWS ONLY without LIMIT",
+                    fetch,
+                ));
+            }
+            limit = Some(match &fetch.quantity {
+                Some(v) => Limit(v).try_into()?,
+                None => 1,
+            });
+        }
+
+        Ok(Sql {
+            selection,
+            condition,
+            source,
+            order_by,
+            offset,
+            limit,
+            sample,
+            reshapes,
+            unnest,
+            volatile,
+            columns,
+        })
+    }
+}
+
+/// SELECT 里的 `UNNEST(expr) [AS name]`，返回参数和展开后的列名
+fn unnest_item(item: &SelectItem) -> Option<(&SqlExpr, String)> {
+    let (expr, name) = match item {
+        SelectItem::UnnamedExpr(expr) => (expr, expr.to_string()),
+        SelectItem::ExprWithAlias { expr, alias } => (expr, alias.value.clone()),
+        _ => return None,
+    };
+    match expr {
+        SqlExpr::Function(f) if f.name.to_string().eq_ignore_ascii_case("unnest") => {
+            match function_args(f).ok()?.as_slice() {
+                [arg] => Some((*arg, name)),
+                _ => None,
             }
-            _ => Err(anyhow!("We only support Query at the moment")),
         }
+        _ => None,
     }
 }
 
+/// 把 `TABLESAMPLE BERNOULLI (10) REPEATABLE (42)` / `SAMPLE 10 ROWS` 转换成 Sample。
+/// 数据都在内存里，SYSTEM / BLOCK 也按行抽样
+fn table_sample(kind: &TableSampleKind, span: Span) -> Result<Sample> {
+    let sample = match kind {
+        TableSampleKind::BeforeTableAlias(s) | TableSampleKind::AfterTableAlias(s) => s,
+    };
+    let invalid = |message: &str| QueryError::Unsupported {
+        message: message.into(),
+        fragment: sample.to_string(),
+        span,
+    };
+    if sample.bucket.is_some() || sample.offset.is_some() {
+        return Err(invalid("TABLESAMPLE BUCKET and OFFSET are not supported"));
+    }
+
+    let quantity = sample
+        .quantity
+        .as_ref()
+        .ok_or_else(|| invalid("TABLESAMPLE requires a sample size"))?;
+    let value = match &quantity.value {
+        SqlExpr::Value(ValueWithSpan {
+            value: SqlValue::Number(n, _),
+            ..
+        }) => n,
+        _ => return Err(invalid("TABLESAMPLE size must be a number")),
+    };
+    let size = match quantity.unit {
+        Some(TableSampleUnit::Rows) => SampleSize::Rows(
+            value
+                .parse()
+                .map_err(|_| invalid("SAMPLE ROWS must be a non-negative integer"))?,
+        ),
+        Some(TableSampleUnit::Percent) | None => match value.parse::<f64>() {
+            Ok(p) if (0.0..=100.0).contains(&p) => SampleSize::Percent(p),
+            _ => return Err(invalid("TABLESAMPLE percentage must be between 0 and 100")),
+        },
+    };
+
+    let seed = match &sample.seed {
+        Some(seed) => match &seed.value.value {
+            SqlValue::Number(n, _) => Some(
+                n.parse()
+                    .map_err(|_| invalid("TABLESAMPLE seed must be a non-negative integer"))?,
+            ),
+            _ => return Err(invalid("TABLESAMPLE seed must be a number")),
+        },
+        None => None,
+    };
+
+    Ok(Sample { size, seed })
+}
+
+/// 找出 node 里所有用标识符引用的列
+fn column_refs<V: Visit>(node: &V, columns: &mut Vec<Ident>) {
+    let _ = visit_expressions(node, |expr| {
+        if let SqlExpr::Identifier(id) = expr {
+            columns.push(id.clone());
+        }
+        ControlFlow::<()>::Continue(())
+    });
+}
+
+/// node 里是否调用了 NOW()
+fn calls_now<V: Visit>(node: &V) -> bool {
+    visit_expressions(node, |expr| match expr {
+        SqlExpr::Function(f) if f.name.to_string().eq_ignore_ascii_case("now") => {
+            ControlFlow::Break(())
+        }
+        _ => ControlFlow::Continue(()),
+    })
+    .is_break()
+}
+
 /// 把 SqlParser 的 Expr 转换成 DataFrame 的 Expr
-impl TryFrom<Expression> for Expr {
-    type Error = anyhow::Error;
+impl TryFrom<Expression<'_>> for Expr {
+    type Error = QueryError;
 
     fn try_from(expr: Expression) -> Result<Self, Self::Error> {
+        let functions = expr.1;
+        let span = expr.0.span();
         match *expr.0 {
+            // date + INTERVAL '1 day' / date - INTERVAL '1 day'
+            SqlExpr::BinaryOp {
+                left,
+                op: op @ (SqlBinaryOperator::Plus | SqlBinaryOperator::Minus),
+                right,
+            } if matches!(*right, SqlExpr::Interval(_)) => {
+                let interval = interval(&right)?;
+                let left = Expression(left, functions).try_into()?;
+                Ok(temporal::shift(
+                    left,
+                    interval,
+                    op == SqlBinaryOperator::Minus,
+                ))
+            }
+            // INTERVAL '1 day' + date
+            SqlExpr::BinaryOp {
+                left,
+                op: SqlBinaryOperator::Plus,
+                right,
+            } if matches!(*left, SqlExpr::Interval(_)) => {
+                let interval = interval(&left)?;
+                Ok(temporal::shift(
+                    Expression(right, functions).try_into()?,
+                    interval,
+                    false,
+                ))
+            }
+            // payload->'user'->>'name'，连续的 -> 合并成一个路径（括号也一样），
+            // 每行的 JSON 文本只解析一次
+            SqlExpr::BinaryOp {
+                left,
+                op: op @ (SqlBinaryOperator::Arrow | SqlBinaryOperator::LongArrow),
+                right,
+            } => {
+                let mut path = vec![path_segment(&right)?];
+                let mut left = left;
+                loop {
+                    match *left {
+                        SqlExpr::BinaryOp {
+                            left: inner,
+                            op: SqlBinaryOperator::Arrow,
+                            right,
+                        } => {
+                            path.push(path_segment(&right)?);
+                            left = inner;
+                        }
+                        SqlExpr::Nested(inner) => left = inner,
+                        expr => {
+                            left = Box::new(expr);
+                            break;
+                        }
+                    }
+                }
+                path.reverse();
+                let output = match op {
+                    SqlBinaryOperator::Arrow => json::Output::Json,
+                    _ => json::Output::Text,
+                };
+                Ok(json::extract(
+                    Expression(left, functions).try_into()?,
+                    path,
+                    output,
+                ))
+            }
+            // name ~ 'pattern'，~* 不区分大小写，!~ / !~* 是不匹配
+            SqlExpr::BinaryOp {
+                left,
+                op:
+                    op @ (SqlBinaryOperator::PGRegexMatch
+                    | SqlBinaryOperator::PGRegexIMatch
+                    | SqlBinaryOperator::PGRegexNotMatch
+                    | SqlBinaryOperator::PGRegexNotIMatch),
+                right,
+            } => {
+                let flags = regexp::Flags {
+                    case_insensitive: matches!(
+                        op,
+                        SqlBinaryOperator::PGRegexIMatch | SqlBinaryOperator::PGRegexNotIMatch
+                    ),
+                    global: false,
+                };
+                let re = regex_arg(&right, flags)?;
+                let matched = regexp::matches(Expression(left, functions).try_into()?, re);
+                Ok(match op {
+                    SqlBinaryOperator::PGRegexMatch | SqlBinaryOperator::PGRegexIMatch => matched,
+                    _ => matched.not(),
+                })
+            }
             SqlExpr::BinaryOp { left, op, right } => Ok(Expr::BinaryExpr {
-                left: Box::new(Expression(left).try_into()?),
-                op: Operation(op).try_into()?,
-                right: Box::new(Expression(right).try_into()?),
+                left: Box::new(Expression(left, functions).try_into()?),
+                op: Operation(op, span).try_into()?,
+                right: Box::new(Expression(right, functions).try_into()?),
             }),
-            SqlExpr::Wildcard => Ok(Self::Wildcard),
-            SqlExpr::IsNull(expr) => Ok(Self::IsNull(Box::new(Expression(expr).try_into()?))),
-            SqlExpr::IsNotNull(expr) => Ok(Self::IsNotNull(Box::new(Expression(expr).try_into()?))),
+            SqlExpr::Wildcard(_) => Ok(Self::Wildcard),
+            SqlExpr::IsNull(expr) => Ok(Self::IsNull(Box::new(
+                Expression(expr, functions).try_into()?,
+            ))),
+            SqlExpr::IsNotNull(expr) => Ok(Self::IsNotNull(Box::new(
+                Expression(expr, functions).try_into()?,
+            ))),
             SqlExpr::Identifier(id) => Ok(Self::Column(Arc::new(id.value))),
+            SqlExpr::Nested(expr) => Expression(expr, functions).try_into(),
             SqlExpr::Value(v) => Ok(Self::Literal(Value(v).try_into()?)),
-            v => Err(anyhow!("expr {:#?} is not supported", v)),
+            SqlExpr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => {
+                let expr: Expr = Expression(expr, functions).try_into()?;
+                let low = Expression(low, functions).try_into()?;
+                let high = Expression(high, functions).try_into()?;
+                let between = expr.clone().gt_eq(low).and(expr.lt_eq(high));
+                Ok(if negated { between.not() } else { between })
+            }
+            SqlExpr::TypedString(TypedString {
+                data_type, value, ..
+            }) => {
+                let invalid = || QueryError::unsupported("Invalid date/time literal", &value);
+                let s = match &value.value {
+                    SqlValue::SingleQuotedString(s) => s,
+                    _ => return Err(invalid()),
+                };
+                match data_type {
+                    SqlDataType::Date => {
+                        let date = temporal::parse_date(s).ok_or_else(invalid)?;
+                        Ok(lit(date).cast(DataType::Date32))
+                    }
+                    SqlDataType::Timestamp(..) | SqlDataType::Datetime(_) => {
+                        Ok(lit(temporal::parse_datetime(s).ok_or_else(invalid)?))
+                    }
+                    _ => Err(QueryError::unsupported(
+                        "Only DATE and TIMESTAMP literals are supported",
+                        &value,
+                    )),
+                }
+            }
+            SqlExpr::Extract { field, expr, .. } => {
+                let field = match field {
+                    DateTimeField::Year | DateTimeField::Years => DateField::Year,
+                    DateTimeField::Quarter => DateField::Quarter,
+                    DateTimeField::Month | DateTimeField::Months => DateField::Month,
+                    DateTimeField::Week(None) | DateTimeField::Weeks => DateField::Week,
+                    DateTimeField::Day | DateTimeField::Days => DateField::Day,
+                    DateTimeField::DayOfWeek | DateTimeField::Dow => DateField::DayOfWeek,
+                    DateTimeField::Isodow => DateField::IsoDayOfWeek,
+                    DateTimeField::DayOfYear | DateTimeField::Doy => DateField::DayOfYear,
+                    DateTimeField::Hour | DateTimeField::Hours => DateField::Hour,
+                    DateTimeField::Minute | DateTimeField::Minutes => DateField::Minute,
+                    DateTimeField::Second | DateTimeField::Seconds => DateField::Second,
+                    field => {
+                        return Err(QueryError::Unsupported {
+                            message: "EXTRACT field is not supported".into(),
+                            fragment: field.to_string(),
+                            span,
+                        })
+                    }
+                };
+                Ok(temporal::extract(
+                    field,
+                    Expression(expr, functions).try_into()?,
+                ))
+            }
+            SqlExpr::Function(f) => Function(f, functions).try_into(),
+            // SIMILAR TO 转换成正则表达式，默认的转义字符是反斜杠
+            SqlExpr::SimilarTo {
+                negated,
+                expr,
+                pattern,
+                escape_char,
+            } => {
+                let escape = match &escape_char {
+                    Some(e) => {
+                        let e = string_literal(e).ok_or_else(|| {
+                            QueryError::unsupported("ESCAPE must be a string literal", &**e)
+                        })?;
+                        e.chars().next()
+                    }
+                    None => Some('\\'),
+                };
+                let similar = string_literal(&pattern).ok_or_else(|| {
+                    QueryError::unsupported(
+                        "SIMILAR TO pattern must be a string literal",
+                        &*pattern,
+                    )
+                })?;
+                let re = regexp::compile(
+                    &regexp::similar_to(similar, escape),
+                    regexp::Flags::default(),
+                    pattern.span(),
+                )?;
+                let matched = regexp::matches(Expression(expr, functions).try_into()?, re);
+                Ok(if negated { matched.not() } else { matched })
+            }
+            SqlExpr::Cast {
+                kind: CastKind::Cast | CastKind::DoubleColon,
+                expr,
+                data_type,
+                format: None,
+            } => {
+                let dtype = match data_type {
+                    SqlDataType::SmallInt(_)
+                    | SqlDataType::Int(_)
+                    | SqlDataType::Integer(_)
+                    | SqlDataType::BigInt(_)
+                    | SqlDataType::Int64 => DataType::Int64,
+                    SqlDataType::Real
+                    | SqlDataType::Float(_)
+                    | SqlDataType::Float64
+                    | SqlDataType::Double(_)
+                    | SqlDataType::DoublePrecision
+                    | SqlDataType::Numeric(_)
+                    | SqlDataType::Decimal(_) => DataType::Float64,
+                    SqlDataType::Text
+                    | SqlDataType::String(_)
+                    | SqlDataType::Varchar(_)
+                    | SqlDataType::Char(_) => DataType::Utf8,
+                    SqlDataType::Bool | SqlDataType::Boolean => DataType::Boolean,
+                    dtype => {
+                        return Err(QueryError::Unsupported {
+                            message: "CAST type is not supported".into(),
+                            fragment: dtype.to_string(),
+                            span,
+                        })
+                    }
+                };
+                let expr: Expr = Expression(expr, functions).try_into()?;
+                Ok(expr.cast(dtype))
+            }
+            // CASE [operand] WHEN ... THEN ... ELSE ... END，没有 ELSE 时结果是 null
+            SqlExpr::Case {
+                operand,
+                conditions,
+                else_result,
+                ..
+            } => {
+                let operand: Option<Expr> = match operand {
+                    Some(o) => Some(Expression(o, functions).try_into()?),
+                    None => None,
+                };
+                let mut branches = Vec::with_capacity(conditions.len());
+                for CaseWhen { condition, result } in conditions {
+                    let condition: Expr = Expression(Box::new(condition), functions).try_into()?;
+                    let condition = match &operand {
+                        Some(o) => o.clone().eq(condition),
+                        None => condition,
+                    };
+                    branches.push((
+                        condition,
+                        Expression(Box::new(result), functions).try_into()?,
+                    ));
+                }
+                let otherwise = match else_result {
+                    Some(e) => Expression(e, functions).try_into()?,
+                    None => lit(Null {}),
+                };
+                Ok(branches
+                    .into_iter()
+                    .rev()
+                    .fold(otherwise, |acc, (condition, result)| {
+                        when(condition).then(result).otherwise(acc)
+                    }))
+            }
+            v => Err(QueryError::unsupported("expr is not supported", &v)),
+        }
+    }
+}
+
+/// 字符串常量的值
+fn string_literal(expr: &SqlExpr) -> Option<&str> {
+    match expr {
+        SqlExpr::Value(ValueWithSpan {
+            value: SqlValue::SingleQuotedString(s),
+            ..
+        }) => Some(s),
+        _ => None,
+    }
+}
+
+/// 编译作为参数的正则表达式，只支持字符串常量，这样写错的模式在执行前就能发现
+fn regex_arg(expr: &SqlExpr, flags: regexp::Flags) -> Result<Regex> {
+    let pattern = string_literal(expr).ok_or_else(|| {
+        QueryError::unsupported("regular expression must be a string literal", expr)
+    })?;
+    regexp::compile(pattern, flags, expr.span())
+}
+
+/// 正则函数最后的选项参数，比如 `'gi'`
+fn regex_flags(expr: Option<&&SqlExpr>) -> Result<regexp::Flags> {
+    match expr {
+        None => Ok(regexp::Flags::default()),
+        Some(expr) => string_literal(expr)
+            .and_then(regexp::Flags::parse)
+            .ok_or_else(|| QueryError::unsupported("regex flags must be 'g', 'i' or 'gi'", *expr)),
+    }
+}
+
+/// `->` 右边的字段名或者数组下标
+fn path_segment(expr: &SqlExpr) -> Result<json::PathSegment> {
+    match expr {
+        SqlExpr::Value(ValueWithSpan {
+            value: SqlValue::SingleQuotedString(key),
+            ..
+        }) => Ok(json::PathSegment::Key(key.clone())),
+        expr => match eval_integer(expr) {
+            Some(i) => Ok(json::PathSegment::Index(i)),
+            None => Err(QueryError::unsupported(
+                "JSON operator expects a quoted key or an integer index",
+                expr,
+            )),
+        },
+    }
+}
+
+/// 把 `INTERVAL '7 days'` / `INTERVAL '7' DAY` 转换成 Interval
+fn interval(expr: &SqlExpr) -> Result<Interval> {
+    let invalid = || QueryError::unsupported("Invalid interval", expr);
+    let interval = match expr {
+        SqlExpr::Interval(i) => i,
+        _ => return Err(invalid()),
+    };
+    let value = match interval.value.as_ref() {
+        SqlExpr::Value(v) => match &v.value {
+            SqlValue::SingleQuotedString(s) | SqlValue::Number(s, _) => s,
+            _ => return Err(invalid()),
+        },
+        _ => return Err(invalid()),
+    };
+    let unit = interval
+        .leading_field
+        .as_ref()
+        .map(|f| f.to_string().to_lowercase());
+    Interval::parse(value, unit.as_deref()).ok_or_else(invalid)
+}
+
+/// 把 SqlParser 的函数调用转换成 DataFrame 的 Expr
+impl TryFrom<Function<'_>> for Expr {
+    type Error = QueryError;
+
+    fn try_from(f: Function) -> Result<Self, Self::Error> {
+        let functions = f.1;
+        let f = &f.0;
+        let args = function_args(f)?;
+        let name = f.name.to_string().to_lowercase();
+        match (name.as_str(), args.as_slice()) {
+            ("now", []) => Ok(temporal::now()),
+            ("json_extract", [expr, path]) => {
+                let invalid =
+                    || QueryError::unsupported("JSON path must look like '$.a.b[0]'", *path);
+                let path = match path {
+                    SqlExpr::Value(ValueWithSpan {
+                        value: SqlValue::SingleQuotedString(s),
+                        ..
+                    }) => json::parse_path(s).ok_or_else(invalid)?,
+                    _ => return Err(invalid()),
+                };
+                let expr = Expression(Box::new((*expr).clone()), functions).try_into()?;
+                Ok(json::extract(expr, path, json::Output::Text))
+            }
+            ("regexp_matches", [expr, pattern, rest @ ..]) if rest.len() <= 1 => {
+                let flags = regex_flags(rest.first())?;
+                let re = regex_arg(pattern, flags)?;
+                let expr = Expression(Box::new((*expr).clone()), functions).try_into()?;
+                Ok(regexp::matches(expr, re))
+            }
+            ("regexp_replace", [expr, pattern, replacement, rest @ ..]) if rest.len() <= 1 => {
+                let flags = regex_flags(rest.first())?;
+                let re = regex_arg(pattern, flags)?;
+                let replacement = string_literal(replacement).ok_or_else(|| {
+                    QueryError::unsupported("replacement must be a string literal", *replacement)
+                })?;
+                let expr = Expression(Box::new((*expr).clone()), functions).try_into()?;
+                Ok(regexp::replace(expr, re, replacement, flags.global))
+            }
+            ("regexp_extract", [expr, pattern, rest @ ..]) if rest.len() <= 1 => {
+                let re = regex_arg(pattern, regexp::Flags::default())?;
+                let group = match rest.first() {
+                    Some(group) => match eval_integer(group) {
+                        Some(g) if g >= 0 && (g as usize) < re.captures_len() => g as usize,
+                        _ => {
+                            return Err(QueryError::unsupported(
+                                format!(
+                                    "group must be an integer between 0 and {}",
+                                    re.captures_len() - 1
+                                ),
+                                *group,
+                            ))
+                        }
+                    },
+                    None => 0,
+                };
+                let expr = Expression(Box::new((*expr).clone()), functions).try_into()?;
+                Ok(regexp::extract(expr, re, group))
+            }
+            ("unnest", _) => Err(QueryError::unsupported(
+                "UNNEST is only supported as a top-level item of SELECT",
+                f,
+            )),
+            ("date_trunc", [unit, expr]) => {
+                let invalid = || QueryError::unsupported("DATE_TRUNC unit is not supported", *unit);
+                let unit = match unit {
+                    SqlExpr::Value(ValueWithSpan {
+                        value: SqlValue::SingleQuotedString(s),
+                        ..
+                    }) => s,
+                    _ => return Err(invalid()),
+                };
+                let expr = Expression(Box::new((*expr).clone()), functions).try_into()?;
+                temporal::date_trunc(unit, expr).ok_or_else(invalid)
+            }
+            // 注册过的自定义函数
+            _ => match functions.get(&name) {
+                Some(udf) if udf.args.is_empty() => Err(QueryError::unsupported(
+                    "user-defined function must take at least one argument",
+                    f,
+                )),
+                Some(udf) if udf.args.len() != args.len() => Err(QueryError::unsupported(
+                    format!("function expects {} argument(s)", udf.args.len()),
+                    f,
+                )),
+                Some(udf) => {
+                    let mut exprs = Vec::with_capacity(args.len());
+                    for arg in args {
+                        exprs.push(Expression(Box::new(arg.clone()), functions).try_into()?);
+                    }
+                    Ok(udf.call(exprs))
+                }
+                None => Err(QueryError::unsupported("function is not supported", f)),
+            },
         }
     }
 }
 
+/// 取出函数的参数，只支持普通的位置参数
+fn function_args(f: &SqlFunction) -> Result<Vec<&SqlExpr>> {
+    let list = match &f.args {
+        FunctionArguments::None => return Ok(vec![]),
+        FunctionArguments::List(list)
+            if list.duplicate_treatment.is_none() && list.clauses.is_empty() =>
+        {
+            list
+        }
+        _ => {
+            return Err(QueryError::unsupported(
+                "function arguments are not supported",
+                f,
+            ))
+        }
+    };
+    list.args
+        .iter()
+        .map(|arg| match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Ok(expr),
+            arg => Err(QueryError::unsupported(
+                "function argument is not supported",
+                arg,
+            )),
+        })
+        .collect()
+}
+
 /// 把 SqlParser 的 BinaryOperator 转换成 DataFrame 的 Operator
 impl TryFrom<Operation> for Operator {
-    type Error = anyhow::Error;
+    type Error = QueryError;
 
     fn try_from(op: Operation) -> Result<Self, Self::Error> {
         match op.0 {
@@ -126,103 +858,333 @@ impl TryFrom<Operation> for Operator {
             SqlBinaryOperator::NotEq => Ok(Self::NotEq),
             SqlBinaryOperator::And => Ok(Self::And),
             SqlBinaryOperator::Or => Ok(Self::Or),
-            v => Err(anyhow!("Operator {} is not supported", v)),
+            v => Err(QueryError::Unsupported {
+                message: "Operator is not supported".into(),
+                fragment: v.to_string(),
+                span: op.1,
+            }),
         }
     }
 }
 
 /// 把 SqlParser 的 SelectItem 转换成 DataFrame 的 Expr
 impl<'a> TryFrom<Projection<'a>> for Expr {
-    type Error = anyhow::Error;
+    type Error = QueryError;
 
     fn try_from(p: Projection<'a>) -> Result<Self, Self::Error> {
+        let functions = p.1;
         match p.0 {
-            SelectItem::UnnamedExpr(SqlExpr::Identifier(id)) => Ok(col(&id.to_string())),
-            SelectItem::ExprWithAlias {
-                expr: SqlExpr::Identifier(id),
-                alias,
-            } => Ok(Expr::Alias(
-                Box::new(Expr::Column(Arc::new(id.to_string()))),
-                Arc::new(alias.to_string()),
-            )),
-            SelectItem::QualifiedWildcard(v) => Ok(col(&v.to_string())),
-            SelectItem::Wildcard => Ok(col("*")),
-            item => Err(anyhow!("projection {} not supported", item)),
+            SelectItem::UnnamedExpr(SqlExpr::Identifier(id)) => Ok(col(&id.value)),
+            // 其它表达式用 SQL 原文作为列名
+            SelectItem::UnnamedExpr(expr) => {
+                let e: Expr = Expression(Box::new(expr.clone()), functions).try_into()?;
+                Ok(e.alias(&expr.to_string()))
+            }
+            SelectItem::ExprWithAlias { expr, alias } => {
+                let e: Expr = Expression(Box::new(expr.clone()), functions).try_into()?;
+                Ok(e.alias(&alias.value))
+            }
+            SelectItem::QualifiedWildcard(SelectItemQualifiedWildcardKind::ObjectName(v), _) => {
+                Ok(col(&v.to_string()))
+            }
+            SelectItem::Wildcard(_) => Ok(col("*")),
+            item => Err(QueryError::unsupported("projection not supported", item)),
         }
     }
 }
 
 impl<'a> TryFrom<Source<'a>> for &'a str {
-    type Error = anyhow::Error;
+    type Error = QueryError;
 
     fn try_from(source: Source<'a>) -> Result<Self, Self::Error> {
-        if source.0.len() != 1 {
-            return Err(anyhow!("We only support single data source at the moment"));
+        let table = match source.0 {
+            [table] => table,
+            [] => {
+                return Err(QueryError::Unsupported {
+                    message: "We only support querying from a data source".into(),
+                    fragment: String::new(),
+                    span: Span::empty(),
+                })
+            }
+            [_, other, ..] => {
+                return Err(QueryError::unsupported(
+                    "We only support single data source at the moment",
+                    other,
+                ))
+            }
+        };
+
+        if let Some(join) = table.joins.first() {
+            return Err(QueryError::unsupported(
+                "We do not support joint data source at the moment",
+                &join.relation,
+            ));
         }
 
-        let table = &source.0[0];
-        if !table.joins.is_empty() {
-            return Err(anyhow!("We do not support joint data source at the moment"));
+        match base_table(&table.relation) {
+            TableFactor::Table { name, .. } => table_name_to_str(name),
+            relation => Err(QueryError::unsupported("We only support table", relation)),
         }
+    }
+}
+
+/// PIVOT / UNPIVOT 作用的数据源
+fn base_table(relation: &TableFactor) -> &TableFactor {
+    match relation {
+        TableFactor::Pivot { table, .. } | TableFactor::Unpivot { table, .. } => base_table(table),
+        relation => relation,
+    }
+}
+
+/// 从里到外收集 FROM 里的 PIVOT / UNPIVOT
+fn collect_reshapes(relation: &TableFactor, reshapes: &mut Vec<Reshape>) -> Result<()> {
+    match relation {
+        TableFactor::Pivot {
+            table,
+            aggregate_functions,
+            value_column,
+            value_source,
+            default_on_null,
+            ..
+        } => {
+            collect_reshapes(table, reshapes)?;
+            if default_on_null.is_some() {
+                return Err(QueryError::unsupported(
+                    "PIVOT DEFAULT ON NULL is not supported",
+                    relation,
+                ));
+            }
+
+            let (aggregate, value) = match aggregate_functions.as_slice() {
+                [f] if f.alias.is_none() => pivot_aggregate(&f.expr)?,
+                _ => {
+                    return Err(QueryError::unsupported(
+                        "PIVOT only supports a single aggregate function without alias",
+                        relation,
+                    ))
+                }
+            };
+            let pivot = match value_column.as_slice() {
+                [SqlExpr::Identifier(id)] => id.clone(),
+                _ => {
+                    return Err(QueryError::unsupported(
+                        "PIVOT FOR only supports a single column",
+                        relation,
+                    ))
+                }
+            };
+            let values = match value_source {
+                PivotValueSource::List(values) => values,
+                _ => {
+                    return Err(QueryError::unsupported(
+                        "PIVOT IN only supports a list of values",
+                        relation,
+                    ))
+                }
+            };
 
-        match &table.relation {
-            TableFactor::Table { name, .. } => Ok(&name.0.first().unwrap().value),
-            _ => Err(anyhow!("We only support table")),
+            let mut columns = Vec::with_capacity(values.len());
+            for v in values {
+                let (key, value) = match &v.expr {
+                    SqlExpr::Value(
+                        value @ ValueWithSpan {
+                            value: SqlValue::SingleQuotedString(s) | SqlValue::Number(s, _),
+                            ..
+                        },
+                    ) => (s.clone(), Value(value.clone()).try_into()?),
+                    expr => {
+                        return Err(QueryError::unsupported(
+                            "PIVOT IN only supports string and number values",
+                            expr,
+                        ))
+                    }
+                };
+                columns.push(PivotColumn {
+                    value: Expr::Literal(value),
+                    alias: v
+                        .alias
+                        .as_ref()
+                        .map_or_else(|| key.clone(), |a| a.value.clone()),
+                    key,
+                });
+            }
+
+            reshapes.push(Reshape::Pivot {
+                aggregate,
+                value,
+                pivot,
+                columns,
+            });
+        }
+        TableFactor::Unpivot {
+            table,
+            value,
+            name,
+            columns,
+            null_inclusion,
+            ..
+        } => {
+            collect_reshapes(table, reshapes)?;
+            let value = match value {
+                SqlExpr::Identifier(id) => id.value.clone(),
+                expr => {
+                    return Err(QueryError::unsupported(
+                        "UNPIVOT value must be a column name",
+                        expr,
+                    ))
+                }
+            };
+            let columns = columns
+                .iter()
+                .map(|c| match &c.expr {
+                    SqlExpr::Identifier(id) => {
+                        Ok((id.clone(), c.alias.as_ref().unwrap_or(id).value.clone()))
+                    }
+                    expr => Err(QueryError::unsupported(
+                        "UNPIVOT IN only supports column names",
+                        expr,
+                    )),
+                })
+                .collect::<Result<_>>()?;
+
+            // 和 SQL 标准一样，默认去掉值是 null 的行
+            reshapes.push(Reshape::Unpivot {
+                value,
+                name: name.value.clone(),
+                columns,
+                include_nulls: matches!(null_inclusion, Some(NullInclusion::IncludeNulls)),
+            });
         }
+        _ => {}
     }
+    Ok(())
+}
+
+/// 把 PIVOT 里的 `SUM(new_cases)` 转换成聚合函数和要聚合的列
+fn pivot_aggregate(expr: &SqlExpr) -> Result<(PivotAggregate, Ident)> {
+    let invalid = || QueryError::unsupported("PIVOT aggregate is not supported", expr);
+    let f = match expr {
+        SqlExpr::Function(f) => f,
+        _ => return Err(invalid()),
+    };
+    let aggregate = match f.name.to_string().to_lowercase().as_str() {
+        "sum" => PivotAggregate::Sum,
+        "min" => PivotAggregate::Min,
+        "max" => PivotAggregate::Max,
+        "avg" | "mean" => PivotAggregate::Avg,
+        "median" => PivotAggregate::Median,
+        "count" => PivotAggregate::Count,
+        "first" => PivotAggregate::First,
+        _ => return Err(invalid()),
+    };
+    match function_args(f)?.as_slice() {
+        [SqlExpr::Identifier(id)] => Ok((aggregate, id.clone())),
+        _ => Err(invalid()),
+    }
+}
+
+/// 数据源的名字就是表名的第一段
+fn table_name_to_str(name: &ObjectName) -> Result<&str> {
+    name.0
+        .first()
+        .and_then(|part| part.as_ident())
+        .map(|ident| ident.value.as_str())
+        .ok_or_else(|| QueryError::unsupported("invalid source name", name))
 }
 
 /// 把 SqlParser 的 order by expr 转换成 (列名, 排序方法)
-impl<'a> TryFrom<Order<'a>> for (String, bool) {
-    type Error = anyhow::Error;
+impl<'a> TryFrom<Order<'a>> for (Expr, bool) {
+    type Error = QueryError;
 
     fn try_from(o: Order) -> Result<Self, Self::Error> {
-        let name = match &o.0.expr {
-            SqlExpr::Identifier(id) => id.to_string(),
-            expr => {
-                return Err(anyhow!(
-                    "We only support identifier for order by, got {}",
-                    expr
-                ))
-            }
-        };
-
-        Ok((name, !o.0.asc.unwrap_or(true)))
+        let functions = o.1;
+        let expr = Expression(Box::new(o.0.expr.clone()), functions).try_into()?;
+        Ok((expr, matches!(o.0.options.sort, Some(OrderBySort::Desc))))
     }
 }
 
 /// 把 SqlParser 的 offset expr 转换成 i64
-impl<'a> From<Offset<'a>> for i64 {
-    fn from(offset: Offset) -> Self {
-        match offset.0 {
-            SqlOffset {
-                value: SqlExpr::Value(SqlValue::Number(v, _b)),
-                ..
-            } => v.parse().unwrap_or(0),
-            _ => 0,
-        }
+impl<'a> TryFrom<Offset<'a>> for i64 {
+    type Error = QueryError;
+
+    fn try_from(offset: Offset) -> Result<Self, Self::Error> {
+        row_count(offset.0, "OFFSET")
     }
 }
 
 /// 把 SqlParser 的 Limit expr 转换成 usize
-impl<'a> From<Limit<'a>> for usize {
-    fn from(l: Limit<'a>) -> Self {
-        match l.0 {
-            SqlExpr::Value(SqlValue::Number(v, _b)) => v.parse().unwrap_or(usize::MAX),
-            _ => usize::MAX,
+impl<'a> TryFrom<Limit<'a>> for usize {
+    type Error = QueryError;
+
+    fn try_from(l: Limit<'a>) -> Result<Self, Self::Error> {
+        Ok(row_count(l.0, "LIMIT")? as usize)
+    }
+}
+
+/// LIMIT / OFFSET 的值，必须是非负的整数常量表达式
+fn row_count(expr: &SqlExpr, clause: &str) -> Result<i64> {
+    match eval_integer(expr) {
+        Some(n) if n >= 0 => Ok(n),
+        _ => Err(QueryError::unsupported(
+            format!("{} must be a non-negative integer", clause),
+            expr,
+        )),
+    }
+}
+
+/// 计算整数常量表达式，比如 `10`、`(2 + 3) * 4`
+fn eval_integer(expr: &SqlExpr) -> Option<i64> {
+    match expr {
+        SqlExpr::Value(v) => match &v.value {
+            SqlValue::Number(n, _) => n.parse().ok(),
+            _ => None,
+        },
+        SqlExpr::Nested(expr) => eval_integer(expr),
+        // 绑定的整数参数，比如 `LIMIT $1`
+        SqlExpr::Cast {
+            expr,
+            data_type: SqlDataType::BigInt(_),
+            ..
+        } => eval_integer(expr),
+        SqlExpr::UnaryOp { op, expr } => match op {
+            UnaryOperator::Plus => eval_integer(expr),
+            UnaryOperator::Minus => eval_integer(expr)?.checked_neg(),
+            _ => None,
+        },
+        SqlExpr::BinaryOp { left, op, right } => {
+            let (l, r) = (eval_integer(left)?, eval_integer(right)?);
+            match op {
+                SqlBinaryOperator::Plus => l.checked_add(r),
+                SqlBinaryOperator::Minus => l.checked_sub(r),
+                SqlBinaryOperator::Multiply => l.checked_mul(r),
+                SqlBinaryOperator::Divide => l.checked_div(r),
+                SqlBinaryOperator::Modulo => l.checked_rem(r),
+                _ => None,
+            }
         }
+        _ => None,
     }
 }
 
 /// 把 SqlParser 的 value 转换成 DataFrame 支持的 LiteralValue
 impl TryFrom<Value> for LiteralValue {
-    type Error = anyhow::Error;
+    type Error = QueryError;
     fn try_from(v: Value) -> Result<Self, Self::Error> {
-        match v.0 {
-            SqlValue::Number(v, _) => Ok(LiteralValue::Float64(v.parse().unwrap())),
-            SqlValue::Boolean(v) => Ok(LiteralValue::Boolean(v)),
+        let unsupported = |message| QueryError::unsupported(message, &v.0);
+        match &v.0.value {
+            // 数字常量都按 Float64 处理，绑定的整数参数是 `CAST(n AS BIGINT)`，见 bind_params
+            SqlValue::Number(n, _) => n
+                .parse()
+                .map(LiteralValue::Float64)
+                .map_err(|_| unsupported("Number is out of range")),
+            SqlValue::SingleQuotedString(s) => Ok(LiteralValue::Utf8(s.clone())),
+            SqlValue::Boolean(b) => Ok(LiteralValue::Boolean(*b)),
+            SqlValue::Placeholder(p) => Err(QueryError::Param(format!(
+                "No value bound to placeholder {}",
+                p
+            ))),
             SqlValue::Null => Ok(LiteralValue::Null),
-            v => Err(anyhow!("Value {} is not supported", v)),
+            _ => Err(unsupported("Value is not supported")),
         }
     }
 }
@@ -230,6 +1192,7 @@ impl TryFrom<Value> for LiteralValue {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dialect::parse_sql;
     use crate::TyrDialect;
     use sqlparser::parser::Parser;
     use std::convert::TryInto;
@@ -238,15 +1201,135 @@ mod tests {
     fn parse_sql_works() {
         let url = "http://abc.xyz/abc?a=1&b=2";
         let sql = format!(
-            "select a, b, c from {} where a=1 order by c desc limit 5 offset 10",
+            "select a, b, c from '{}' where a=1 order by c desc limit 5 offset 10",
             url
         );
-        let statement = &Parser::parse_sql(&TyrDialect::default(), sql.as_ref()).unwrap()[0];
+        let statement = &parse_sql(&sql).unwrap()[0];
         let sql: Sql = statement.try_into().unwrap();
         assert_eq!(sql.source, url);
         assert_eq!(sql.limit, Some(5));
         assert_eq!(sql.offset, Some(10));
-        assert_eq!(sql.order_by, vec![("c".into(), true)]);
+        assert_eq!(sql.order_by, vec![(col("c"), true)]);
         assert_eq!(sql.selection, vec![col("a"), col("b"), col("c")]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn json_arrow_chain_is_one_extract() {
+        let sql = "SELECT payload->'user'->>'name', (payload->'items')->0->'sku' FROM t";
+        let statement = &parse_sql(sql).unwrap()[0];
+        let sql: Sql = statement.try_into().unwrap();
+        for expr in &sql.selection {
+            assert_eq!(format!("{:?}", expr).matches("APPLY").count(), 1);
+        }
+    }
+
+    #[test]
+    fn parse_describe_works() {
+        for sql in ["DESCRIBE abc", "DESC abc", "SHOW COLUMNS FROM abc"] {
+            let statement = &Parser::parse_sql(&TyrDialect, sql).unwrap()[0];
+            let command: Command = statement.try_into().unwrap();
+            assert!(matches!(command, Command::Describe("abc")));
+        }
+    }
+
+    #[test]
+    fn parse_copy_works() {
+        let sql = "COPY (SELECT a FROM abc) TO 'file:///tmp/out.parquet' (FORMAT parquet)";
+        let statement = &Parser::parse_sql(&TyrDialect, sql).unwrap()[0];
+        match statement.try_into().unwrap() {
+            Command::Copy {
+                query,
+                target,
+                format,
+            } => {
+                assert_eq!(query.source, "abc");
+                assert_eq!(target, "file:///tmp/out.parquet");
+                assert_eq!(format, Some(Format::Parquet));
+            }
+            _ => panic!("expect COPY"),
+        }
+    }
+
+    #[test]
+    fn unsupported_syntax_reports_fragment_and_span() {
+        let sql = "SELECT a,\n  a IN (SELECT b FROM t) FROM abc";
+        let statement = &Parser::parse_sql(&TyrDialect, sql).unwrap()[0];
+        let err = Sql::try_from(statement).err().unwrap();
+        match &err {
+            QueryError::Unsupported { fragment, span, .. } => {
+                assert_eq!(fragment, "a IN (SELECT b FROM t)");
+                assert_eq!((span.start.line, span.start.column), (2, 3));
+            }
+            e => panic!("expect Unsupported, got {:?}", e),
+        }
+        assert_eq!(
+            err.to_string(),
+            "expr is not supported: a IN (SELECT b FROM t) at Line: 2, Column: 3"
+        );
+    }
+
+    #[test]
+    fn limit_offset_works() {
+        let limit = |sql: &str| -> Result<(Option<usize>, Option<i64>)> {
+            let statement = &parse_sql(sql).unwrap()[0];
+            let sql = Sql::try_from(statement)?;
+            Ok((sql.limit, sql.offset))
+        };
+
+        assert_eq!(
+            limit("SELECT a FROM t LIMIT (2 + 3) * 2 OFFSET -1 + 2").unwrap(),
+            (Some(10), Some(1))
+        );
+        assert_eq!(
+            limit("SELECT a FROM t OFFSET 5 ROWS FETCH FIRST 3 ROWS ONLY").unwrap(),
+            (Some(3), Some(5))
+        );
+        assert_eq!(
+            limit("SELECT a FROM t FETCH FIRST ROW ONLY").unwrap(),
+            (Some(1), None)
+        );
+
+        let err = limit("SELECT a FROM t LIMIT abc").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "LIMIT must be a non-negative integer: abc at Line: 1, Column: 23"
+        );
+        assert!(limit("SELECT a FROM t LIMIT -1").is_err());
+        assert!(limit("SELECT a FROM t OFFSET 1.5").is_err());
+        assert!(limit("SELECT a FROM t FETCH FIRST 10 PERCENT ROWS ONLY").is_err());
+    }
+
+    #[test]
+    fn table_sample_works() {
+        let sample = |sql: &str| -> Result<Option<Sample>> {
+            let statement = &parse_sql(sql).unwrap()[0];
+            Ok(Sql::try_from(statement)?.sample)
+        };
+
+        assert_eq!(sample("SELECT a FROM t").unwrap(), None);
+        assert_eq!(
+            sample("SELECT a FROM t TABLESAMPLE BERNOULLI (10) REPEATABLE (42)").unwrap(),
+            Some(Sample {
+                size: SampleSize::Percent(10.0),
+                seed: Some(42)
+            })
+        );
+        assert_eq!(
+            sample("SELECT a FROM t SAMPLE 5 ROWS WHERE a > 1").unwrap(),
+            Some(Sample {
+                size: SampleSize::Rows(5),
+                seed: None
+            })
+        );
+        assert_eq!(
+            sample("SELECT a FROM t SAMPLE SYSTEM (0.5) SEED (7)").unwrap(),
+            Some(Sample {
+                size: SampleSize::Percent(0.5),
+                seed: Some(7)
+            })
+        );
+
+        assert!(sample("SELECT a FROM t TABLESAMPLE BERNOULLI (150)").is_err());
+        assert!(sample("SELECT a FROM t SAMPLE 1.5 ROWS").is_err());
+    }
+}
@@ -1,27 +1,87 @@
+use sqlparser::ast::Statement;
 use sqlparser::dialect::Dialect;
+use sqlparser::keywords::Keyword;
+use sqlparser::parser::{Parser, ParserError};
+use sqlparser::tokenizer::{Token, Tokenizer};
 
 #[derive(Debug, Default)]
 pub struct TyrDialect;
 
 impl Dialect for TyrDialect {
     fn is_identifier_start(&self, ch: char) -> bool {
-        ('a'..='z').contains(&ch) || ('A'..'Z').contains(&ch) || ch == '_'
+        ch.is_ascii_lowercase() || ch.is_ascii_uppercase() || ch == '_'
     }
 
     fn is_identifier_part(&self, ch: char) -> bool {
-        ('a'..='z').contains(&ch) 
-            || ('A'..='Z').contains(&ch)
-            || ('0'..='9').contains(&ch)
-            || [':', '/', '?', '&', '=', '-', '_', '.'].contains(&ch)
+        ch.is_ascii_lowercase() || ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == '_'
     }
 }
 
+/// 用 TyrDialect 解析 SQL。
+/// 数据源可以写成字符串，如 `FROM 'file:///data/*.csv'`，解析前会把它转成带引号的标识符；
+/// 也可以直接写成带引号的标识符，如 `FROM "data.csv"`。
+/// 只转换数据源位置上的字符串：语句开头的 `DESCRIBE`/`DESC` 之后，或者查询（而不是
+/// `EXTRACT(YEAR FROM '...')` 这样的函数参数）里的 `FROM` 之后，`IS DISTINCT FROM` 除外
+pub fn parse_sql(sql: &str) -> Result<Vec<Statement>, ParserError> {
+    let mut tokens = Tokenizer::new(&TyrDialect, sql).tokenize_with_location()?;
+
+    // 每一层括号是不是查询，最外层是语句本身
+    let mut queries = vec![true];
+    let mut previous: [Option<Keyword>; 2] = [None, None];
+    // 当前 token 是语句里的第几个
+    let mut position = 0;
+    for t in tokens.iter_mut() {
+        if let Token::Whitespace(_) = t.token {
+            continue;
+        }
+        let in_query = queries.last() == Some(&true);
+        if let Token::SingleQuotedString(s) = &t.token {
+            let source = match previous {
+                [Some(Keyword::DESCRIBE | Keyword::DESC), _] => position == 1,
+                [Some(Keyword::FROM), before] => in_query && before != Some(Keyword::DISTINCT),
+                _ => false,
+            };
+            if source {
+                t.token = Token::make_word(s, Some('"'));
+            }
+        }
+        match &t.token {
+            Token::LParen => queries.push(false),
+            Token::RParen if queries.len() > 1 => {
+                queries.pop();
+            }
+            Token::SemiColon => {
+                queries.truncate(1);
+                position = 0;
+                previous = [None, None];
+                continue;
+            }
+            Token::Word(w) if matches!(w.keyword, Keyword::SELECT | Keyword::WITH) => {
+                if let Some(query) = queries.last_mut() {
+                    *query = true;
+                }
+            }
+            _ => {}
+        }
+        let keyword = match &t.token {
+            Token::Word(w) if w.quote_style.is_none() => Some(w.keyword),
+            _ => None,
+        };
+        previous = [keyword, previous[0]];
+        position += 1;
+    }
+
+    Parser::new(&TyrDialect)
+        .with_tokens_with_locations(tokens)
+        .parse_statements()
+}
+
 pub fn example_sql() -> String {
     let url = "https://raw.githubusercontent.com/owid/covid-19-data/master/public/data/latest/owid-covid-latest.csv";
-    
+
     let sql = format!(
         "SELECT location name, total_cases, new_cases, total_deaths, new_deaths \
-        FROM {} where new_deaths >= 500 ORDER BY new_cases DESC LIMIT 6 OFFSET 5",
+        FROM '{}' where new_deaths >= 500 ORDER BY new_cases DESC LIMIT 6 OFFSET 5",
         url
     );
 
@@ -31,11 +91,51 @@ pub fn example_sql() -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use sqlparser::parser::Parser;
 
     #[test]
     fn it_works() {
+        assert!(parse_sql(&example_sql()).is_ok());
+    }
+
+    #[test]
+    fn string_source_works() {
+        let ast = parse_sql("SELECT a FROM 'file:///data/2021-*.csv' WHERE a = 'x'").unwrap();
+        assert_eq!(
+            ast[0].to_string(),
+            r#"SELECT a FROM "file:///data/2021-*.csv" WHERE a = 'x'"#
+        );
 
-        assert!(Parser::parse_sql(&TyrDialect::default(), &example_sql()).is_ok());
+        let ast = parse_sql(r#"SELECT a FROM "data.csv""#).unwrap();
+        assert_eq!(ast[0].to_string(), r#"SELECT a FROM "data.csv""#);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn operators_without_spaces_work() {
+        let ast = parse_sql("SELECT a/b, a-b FROM t WHERE x=1 AND y>=2").unwrap();
+        assert_eq!(
+            ast[0].to_string(),
+            "SELECT a / b, a - b FROM t WHERE x = 1 AND y >= 2"
+        );
+    }
+
+    #[test]
+    fn string_in_expression_is_not_source() {
+        for sql in [
+            "SELECT EXTRACT(YEAR FROM '2021-01-01') FROM t",
+            "SELECT a FROM t WHERE a IS DISTINCT FROM 'x'",
+            "SELECT SUBSTRING(s FROM 'p') FROM t",
+            "SELECT TRIM(BOTH ' ' FROM 'abc') FROM t",
+        ] {
+            let ast = parse_sql(sql).unwrap();
+            assert_eq!(ast[0].to_string(), sql);
+        }
+
+        let ast = parse_sql("SELECT a FROM (SELECT a FROM 'a.csv') WHERE b IN (1)").unwrap();
+        assert_eq!(
+            ast[0].to_string(),
+            r#"SELECT a FROM (SELECT a FROM "a.csv") WHERE b IN (1)"#
+        );
+        let ast = parse_sql("DESCRIBE 'a.csv'").unwrap();
+        assert_eq!(ast[0].to_string(), r#"DESCRIBE "a.csv""#);
+    }
+}
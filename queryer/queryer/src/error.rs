@@ -0,0 +1,118 @@
+use polars::prelude::PolarsError;
+use sqlparser::ast::Spanned;
+use sqlparser::parser::ParserError;
+use sqlparser::tokenizer::Span;
+use std::fmt::Display;
+use std::time::Duration;
+use thiserror::Error;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+pub type Result<T, E = QueryError> = std::result::Result<T, E>;
+
+/// 查询过程中可能出现的错误
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum QueryError {
+    /// SQL 语法错误
+    #[error(transparent)]
+    Parse(#[from] ParserError),
+
+    /// SQL 能解析，但用到了还不支持的语法。fragment 是出问题的那段 SQL，span 是它的位置
+    #[error("{message}: {fragment}{}", .span.start)]
+    Unsupported {
+        message: String,
+        fragment: String,
+        span: Span,
+    },
+
+    /// 正则表达式写得不对，span 是模式在 SQL 里的位置
+    #[error("Invalid regular expression `{pattern}`{}: {message}", .span.start)]
+    InvalidRegex {
+        pattern: String,
+        message: String,
+        span: Span,
+    },
+
+    /// 占位符没有绑定值或者写法不对
+    #[error("{0}")]
+    Param(String),
+
+    /// SQL 里引用的列在数据源里不存在，suggestions 是名字相近的列
+    #[error("Column `{name}` not found{}{}", .span.start, did_you_mean(.suggestions))]
+    UnknownColumn {
+        name: String,
+        span: Span,
+        suggestions: Vec<String>,
+    },
+
+    /// 获取数据源失败
+    #[error("Failed to fetch {uri}: {source}")]
+    Fetch { uri: String, source: BoxError },
+
+    /// 数据加载失败，比如格式不对或者类型和声明的不一致
+    #[error("{0}")]
+    Load(String),
+
+    /// 执行查询或者写出结果时出错
+    #[error(transparent)]
+    Execution(BoxError),
+
+    /// 用到了 `QueryOptions` 禁止的功能，比如只读时的 COPY
+    #[error("{0}")]
+    NotAllowed(String),
+
+    /// 超过了 `Limits` 里设置的上限
+    #[error("{0}")]
+    LimitExceeded(String),
+
+    /// 查询被 `CancelToken` 取消
+    #[error("Query was cancelled")]
+    Cancelled,
+
+    /// 查询超过了 `QueryOptions` 里设置的时间
+    #[error("Query timed out after {0:?}")]
+    Timeout(Duration),
+}
+
+impl QueryError {
+    /// 不支持的语法，记录对应的 SQL 片段和位置
+    pub fn unsupported<T: Spanned + Display>(message: impl Into<String>, node: &T) -> Self {
+        Self::Unsupported {
+            message: message.into(),
+            fragment: node.to_string(),
+            span: node.span(),
+        }
+    }
+
+    pub fn fetch(uri: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        Self::Fetch {
+            uri: uri.into(),
+            source: source.into(),
+        }
+    }
+
+    pub fn execution(source: impl Into<BoxError>) -> Self {
+        Self::Execution(source.into())
+    }
+}
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        return String::new();
+    }
+    let names: Vec<String> = suggestions.iter().map(|s| format!("`{}`", s)).collect();
+    format!(", did you mean {}?", names.join(" or "))
+}
+
+impl From<PolarsError> for QueryError {
+    fn from(e: PolarsError) -> Self {
+        Self::Execution(e.into())
+    }
+}
+
+impl From<std::io::Error> for QueryError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Execution(e.into())
+    }
+}
@@ -1,47 +1,99 @@
-use anyhow::{anyhow, Result};
+use crate::error::{QueryError, Result};
+use crate::limits::check_bytes;
 use async_trait::async_trait;
 use tokio::fs;
 
 #[async_trait]
 pub trait Fetch {
     type Error;
-    async fn fetch(&self) -> Result<String, Self::Error>;
+    async fn fetch(&self) -> Result<Vec<u8>, Self::Error>;
 }
 
-pub async fn retrieve_data(source: impl AsRef<str>) -> Result<String> {
+pub async fn retrieve_data(source: impl AsRef<str>) -> Result<Vec<u8>> {
+    retrieve_data_limited(source, None).await
+}
+
+/// 获取数据源的内容，超过 max_bytes 时停止读取并报错
+pub async fn retrieve_data_limited(
+    source: impl AsRef<str>,
+    max_bytes: Option<u64>,
+) -> Result<Vec<u8>> {
     let name = source.as_ref();
-    match &name[..4] {
-        "http" => UrlFetcher(name).fetch().await,
-        "file" => FileFetcher(name).fetch().await,
-        _ => return Err(anyhow!("We only support http/https/file at the moment")),
+    match name.split_once("://") {
+        Some(("http" | "https", _)) => UrlFetcher(name, max_bytes).fetch().await,
+        Some(("file", _)) => FileFetcher(name, max_bytes).fetch().await,
+        _ => Err(QueryError::fetch(
+            name,
+            "We only support http/https/file at the moment",
+        )),
     }
 }
 
-struct UrlFetcher<'a>(pub(crate) &'a str);
-struct FileFetcher<'a>(pub(crate) &'a str);
+/// 如果 source 是带通配符的本地文件地址，展开成所有匹配的文件地址，否则返回 None
+pub fn expand_glob(source: &str) -> Result<Option<Vec<String>>, QueryError> {
+    let pattern = match source.strip_prefix("file://") {
+        Some(p) if p.contains(['*', '?', '[']) => p,
+        _ => return Ok(None),
+    };
+
+    let mut files = Vec::new();
+    let entries = glob::glob(pattern).map_err(|e| QueryError::fetch(source, e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| QueryError::fetch(source, e))?;
+        files.push(format!("file://{}", path.display()));
+    }
+
+    if files.is_empty() {
+        return Err(QueryError::fetch(source, "No file matches the pattern"));
+    }
+    Ok(Some(files))
+}
+
+struct UrlFetcher<'a>(pub(crate) &'a str, pub(crate) Option<u64>);
+struct FileFetcher<'a>(pub(crate) &'a str, pub(crate) Option<u64>);
 
 #[async_trait]
 impl<'a> Fetch for UrlFetcher<'a> {
-    type Error = anyhow::Error;
+    type Error = QueryError;
 
-    async fn fetch(&self) -> Result<String, Self::Error> {
-        Ok(reqwest::get(self.0).await?.text().await?)
+    async fn fetch(&self) -> Result<Vec<u8>, Self::Error> {
+        let error = |e| QueryError::fetch(self.0, e);
+        let mut response = reqwest::get(self.0).await.map_err(error)?;
+        if let Some(len) = response.content_length() {
+            check_bytes(self.0, len, self.1)?;
+        }
+        // 没有 Content-Length 时边下载边检查
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(error)? {
+            data.extend_from_slice(&chunk);
+            check_bytes(self.0, data.len() as u64, self.1)?;
+        }
+        Ok(data)
     }
 }
 
 #[async_trait]
 impl<'a> Fetch for FileFetcher<'a> {
-    type Error = anyhow::Error;
+    type Error = QueryError;
 
-    async fn fetch(&self) -> Result<String, Self::Error> {
-        Ok(fs::read_to_string(&self.0[7..]).await?)
+    async fn fetch(&self) -> Result<Vec<u8>, Self::Error> {
+        let error = |e| QueryError::fetch(self.0, e);
+        let path = self
+            .0
+            .strip_prefix("file://")
+            .ok_or_else(|| QueryError::fetch(self.0, "expect a file:// address"))?;
+        if self.1.is_some() {
+            let len = fs::metadata(path).await.map_err(error)?.len();
+            check_bytes(self.0, len, self.1)?;
+        }
+        fs::read(path).await.map_err(error)
     }
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Context;
 
     #[tokio::test]
     async fn http_retrieve_works() {
@@ -52,5 +104,23 @@ mod tests {
         );
     }
 
-    
+    #[tokio::test]
+    async fn short_source_name_is_fetch_error() {
+        let ctx = Context::new();
+        for name in ["file", "files", "filex", "http", "f"] {
+            let err = ctx
+                .query(format!("SELECT * FROM {}", name))
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(&err, QueryError::Fetch { uri, .. } if uri == name),
+                "{:?}",
+                err
+            );
+        }
+        assert!(matches!(
+            FileFetcher("file", None).fetch().await,
+            Err(QueryError::Fetch { .. })
+        ));
+    }
 }
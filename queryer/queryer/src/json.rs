@@ -0,0 +1,542 @@
+//! JSON 数据源和 `->`、`->>`、`json_extract`、`UNNEST` 的实现。
+//!
+//! polars 0.16 还没有 struct 类型，所以嵌套的对象（以及元素不全是同一种标量的数组）
+//! 按 JSON 文本保存在 Utf8 列里，取字段时再解析。`a->'b'->>'c'` 这样连续的取值在转换 SQL 时
+//! 合并成一个路径，每行只解析一次；只有把 `->` 的结果再交给另一个 JSON 函数时才会重新解析。
+
+use crate::error::{QueryError, Result};
+use crate::limits::check_rows;
+use polars::prelude::*;
+use serde::de::{self, DeserializeSeed, Deserializer, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// JSON 路径里的一段：对象的字段或者数组的下标，负数下标从末尾数
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(i64),
+}
+
+/// `->` 返回 JSON 文本（字符串带引号），`->>` 和 `json_extract` 返回文本（字符串不带引号）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Json,
+    Text,
+}
+
+/// 解析 `$.a.b[0]["c d"]` 形式的路径
+pub fn parse_path(path: &str) -> Option<Vec<PathSegment>> {
+    let mut rest = path.trim().strip_prefix('$')?;
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('.') {
+            let end = r.find(['.', '[']).unwrap_or(r.len());
+            if end == 0 {
+                return None;
+            }
+            segments.push(PathSegment::Key(r[..end].to_string()));
+            rest = &r[end..];
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']')?;
+            let inner = r[..end].trim();
+            let segment = match inner.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                Some(key) => PathSegment::Key(key.to_string()),
+                None => PathSegment::Index(inner.parse().ok()?),
+            };
+            segments.push(segment);
+            rest = &r[end + 1..];
+        } else {
+            return None;
+        }
+    }
+    Some(segments)
+}
+
+/// 按路径取出 JSON 里的值，结果是 Utf8 列，路径不存在时是 null。
+/// expr 可以是存着 JSON 文本的 Utf8 列，也可以是 List 列
+pub fn extract(expr: Expr, path: Vec<PathSegment>, output: Output) -> Expr {
+    expr.map(
+        move |s| {
+            let mut out: Utf8Chunked = json_values(&s)?
+                .into_iter()
+                .map(|v| lookup(v?, &path).and_then(|v| text(v, output)))
+                .collect();
+            out.rename(s.name());
+            Ok(out.into_series())
+        },
+        GetOutput::from_type(DataType::Utf8),
+    )
+}
+
+/// UNNEST 用的列：List 列保持不变，存着 JSON 数组的 Utf8 列转换成 List 列，之后再由 [`explode`] 展开成多行
+pub fn unnest(expr: Expr) -> Expr {
+    let output = GetOutput::map_field(|field| match field.data_type() {
+        DataType::List(_) => field.clone(),
+        _ => Field::new(field.name(), DataType::List(DataType::Utf8.to_arrow())),
+    });
+    expr.map(
+        |s| match s.dtype() {
+            DataType::List(_) => Ok(s),
+            DataType::Utf8 => {
+                let mut out: ListChunked = s
+                    .utf8()?
+                    .into_iter()
+                    .map(|v| match serde_json::from_str(v?).ok()? {
+                        Value::Array(items) if !items.is_empty() => {
+                            let items: Vec<Option<String>> = items
+                                .into_iter()
+                                .map(|item| text(item, Output::Text))
+                                .collect();
+                            Some(Series::new("", items))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                out.rename(s.name());
+                Ok(out.into_series())
+            }
+            dtype => Err(PolarsError::ComputeError(
+                format!("UNNEST expects a list or JSON array, got {:?}", dtype).into(),
+            )),
+        },
+        output,
+    )
+}
+
+/// 把 List 列展开成多行，其它列的值跟着重复，null 和空的列表不产生行。
+/// polars 自带的 explode 遇到过滤之后的列表会算错下标，这里自己按行号展开
+pub fn explode(df: &DataFrame, name: &str) -> Result<DataFrame> {
+    let lists = df.column(name)?.list()?;
+    let mut rows: Vec<u32> = Vec::new();
+    let mut values: Option<Series> = None;
+    for (i, list) in lists.into_iter().enumerate() {
+        let list = match list {
+            Some(list) if !list.is_empty() => list,
+            _ => continue,
+        };
+        rows.resize(rows.len() + list.len(), i as u32);
+        match &mut values {
+            Some(values) => {
+                values.append(&list)?;
+            }
+            None => values = Some(list),
+        }
+    }
+
+    let mut values = match values {
+        Some(values) => values,
+        None => {
+            let inner = match lists.dtype() {
+                DataType::List(inner) => DataType::from(inner),
+                _ => DataType::Utf8,
+            };
+            Series::new(name, Vec::<Option<i64>>::new()).cast_with_dtype(&inner)?
+        }
+    };
+    values.rename(name);
+    let mut df = df.take(&UInt32Chunked::new_from_slice("", &rows))?;
+    df.replace(name, values)?;
+    Ok(df)
+}
+
+/// CSV 和 JSON 写不了 List 列，把它们转换成 JSON 文本
+pub fn lists_to_text(df: &DataFrame) -> Result<DataFrame> {
+    let mut df = df.clone();
+    for s in df.get_columns().clone() {
+        if let DataType::List(_) = s.dtype() {
+            let mut text: Utf8Chunked = json_values(&s)?
+                .into_iter()
+                .map(|v| v.map(|v| v.to_string()))
+                .collect();
+            text.rename(s.name());
+            df.replace(s.name(), text.into_series())?;
+        }
+    }
+    Ok(df)
+}
+
+/// 把每一行转换成 JSON 的值：Utf8 按 JSON 解析，List 转换成数组，其它类型直接转换
+fn json_values(s: &Series) -> std::result::Result<Vec<Option<Value>>, PolarsError> {
+    Ok(match s.dtype() {
+        DataType::Utf8 => s
+            .utf8()?
+            .into_iter()
+            .map(|v| serde_json::from_str(v?).ok())
+            .collect(),
+        DataType::List(_) => s
+            .list()?
+            .into_iter()
+            .map(|v| {
+                let v = v?;
+                Some(Value::Array(
+                    (0..v.len()).map(|i| scalar(v.get(i))).collect(),
+                ))
+            })
+            .collect(),
+        _ => (0..s.len()).map(|i| Some(scalar(s.get(i)))).collect(),
+    })
+}
+
+fn scalar(v: AnyValue) -> Value {
+    match v {
+        AnyValue::Null => Value::Null,
+        AnyValue::Boolean(v) => v.into(),
+        AnyValue::Utf8(v) => v.into(),
+        AnyValue::Int8(v) => v.into(),
+        AnyValue::Int16(v) => v.into(),
+        AnyValue::Int32(v) => v.into(),
+        AnyValue::Int64(v) => v.into(),
+        AnyValue::UInt8(v) => v.into(),
+        AnyValue::UInt16(v) => v.into(),
+        AnyValue::UInt32(v) => v.into(),
+        AnyValue::UInt64(v) => v.into(),
+        AnyValue::Float32(v) => v.into(),
+        AnyValue::Float64(v) => v.into(),
+        v => v.to_string().into(),
+    }
+}
+
+fn lookup(mut value: Value, path: &[PathSegment]) -> Option<Value> {
+    for segment in path {
+        value = match (segment, value) {
+            (PathSegment::Key(key), Value::Object(mut map)) => map.remove(key)?,
+            (PathSegment::Index(i), Value::Array(mut items)) => {
+                let i = if *i < 0 { items.len() as i64 + i } else { *i };
+                if i < 0 || i as usize >= items.len() {
+                    return None;
+                }
+                items.swap_remove(i as usize)
+            }
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+fn text(value: Value, output: Output) -> Option<String> {
+    match (value, output) {
+        (Value::Null, Output::Text) => None,
+        (Value::String(s), Output::Text) => Some(s),
+        (value, _) => Some(value.to_string()),
+    }
+}
+
+/// 把 JSON 数组或者每行一个对象的 NDJSON 读成 DataFrame。列按第一次出现的顺序排列，
+/// 缺少的字段是 null。嵌套的对象保存成 JSON 文本，可以用 `->`、`->>` 和 `json_extract` 取出里面的字段；
+/// 元素都是同一种标量的数组保存成 List 列。
+/// 记录数超过 max_rows 时立即报错，不再解析后面的内容
+pub fn read_records(text: &str, max_rows: Option<usize>) -> Result<DataFrame> {
+    let invalid = |e: serde_json::Error| QueryError::Load(format!("invalid JSON: {}", e));
+    let mut records = Vec::new();
+    let mut deserializer = serde_json::Deserializer::from_str(text);
+    if text.trim_start().starts_with('[') {
+        let result = Records {
+            records: &mut records,
+            max_rows,
+        }
+        .deserialize(&mut deserializer);
+        check_rows("source", records.len(), max_rows)?;
+        result.map_err(invalid)?;
+    }
+    for value in deserializer.into_iter::<Value>() {
+        records.push(value.map_err(invalid)?);
+        check_rows("source", records.len(), max_rows)?;
+    }
+
+    let mut names: Vec<String> = Vec::new();
+    let mut columns: Vec<Vec<Value>> = Vec::new();
+    for (row, record) in records.into_iter().enumerate() {
+        let record: Map<String, Value> = match record {
+            Value::Object(record) => record,
+            _ => {
+                return Err(QueryError::Load(format!(
+                    "expect JSON objects, record {} is not an object",
+                    row + 1
+                )))
+            }
+        };
+        for (name, value) in record {
+            let i = match names.iter().position(|n| n == &name) {
+                Some(i) => i,
+                None => {
+                    names.push(name);
+                    columns.push(vec![Value::Null; row]);
+                    columns.len() - 1
+                }
+            };
+            columns[i].push(value);
+        }
+        for column in columns.iter_mut() {
+            column.resize(row + 1, Value::Null);
+        }
+    }
+
+    let columns = names
+        .iter()
+        .zip(columns)
+        .map(|(name, values)| to_series(name, values))
+        .collect();
+    Ok(DataFrame::new(columns)?)
+}
+
+/// 逐个读取开头那个 JSON 数组的元素，超过 max_rows 个时停下来
+struct Records<'a> {
+    records: &'a mut Vec<Value>,
+    max_rows: Option<usize>,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for Records<'a> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for Records<'a> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of JSON objects")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(value) = seq.next_element()? {
+            self.records.push(value);
+            if matches!(self.max_rows, Some(max) if self.records.len() > max) {
+                return Err(de::Error::custom("too many records"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Null,
+    Boolean,
+    Int64,
+    Float64,
+    Utf8,
+}
+
+fn kind(v: &Value) -> Option<Kind> {
+    match v {
+        Value::Null => Some(Kind::Null),
+        Value::Bool(_) => Some(Kind::Boolean),
+        Value::Number(n) if n.is_i64() => Some(Kind::Int64),
+        Value::Number(_) => Some(Kind::Float64),
+        Value::String(_) => Some(Kind::Utf8),
+        _ => None,
+    }
+}
+
+/// 同一列里的值决定列的类型：整数和小数混在一起时是 Float64，其它的混合是 Utf8
+fn common_kind<'a>(values: impl IntoIterator<Item = &'a Value>) -> Option<Kind> {
+    let mut common = Kind::Null;
+    for v in values {
+        common = match (common, kind(v)?) {
+            (common, Kind::Null) => common,
+            (Kind::Null, k) => k,
+            (a, b) if a == b => a,
+            (Kind::Int64, Kind::Float64) | (Kind::Float64, Kind::Int64) => Kind::Float64,
+            _ => Kind::Utf8,
+        };
+    }
+    Some(common)
+}
+
+fn to_series(name: &str, values: Vec<Value>) -> Series {
+    if let Some(kind) = common_kind(&values) {
+        return scalars(name, &values, kind);
+    }
+
+    // 数组：元素都是标量时是 List 列
+    let lists = values.iter().all(|v| v.is_array() || v.is_null());
+    let items = values.iter().filter_map(Value::as_array).flatten();
+    if let (true, Some(kind)) = (lists, common_kind(items)) {
+        let mut out: ListChunked = values
+            .iter()
+            .map(|v| v.as_array().map(|items| scalars("", items, kind)))
+            .collect();
+        out.rename(name);
+        return out.into_series();
+    }
+
+    let values: Vec<Option<String>> = values
+        .into_iter()
+        .map(|v| match v {
+            Value::Null => None,
+            v => Some(v.to_string()),
+        })
+        .collect();
+    Series::new(name, values)
+}
+
+fn scalars(name: &str, values: &[Value], kind: Kind) -> Series {
+    match kind {
+        Kind::Boolean => Series::new(name, values.iter().map(Value::as_bool).collect::<Vec<_>>()),
+        Kind::Int64 => Series::new(name, values.iter().map(Value::as_i64).collect::<Vec<_>>()),
+        Kind::Float64 => Series::new(name, values.iter().map(Value::as_f64).collect::<Vec<_>>()),
+        Kind::Null | Kind::Utf8 => {
+            let values: Vec<Option<String>> = values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => None,
+                    Value::String(s) => Some(s.clone()),
+                    v => Some(v.to_string()),
+                })
+                .collect();
+            Series::new(name, values)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempFile;
+    use crate::{Context, LoadOptions, QueryError};
+
+    #[test]
+    fn parse_path_works() {
+        use PathSegment::*;
+        assert_eq!(
+            parse_path("$.a.b[0][\"c d\"]"),
+            Some(vec![
+                Key("a".into()),
+                Key("b".into()),
+                Index(0),
+                Key("c d".into())
+            ])
+        );
+        assert_eq!(parse_path("$"), Some(vec![]));
+        assert_eq!(
+            parse_path("$.items[-1]"),
+            Some(vec![Key("items".into()), Index(-1)])
+        );
+        assert_eq!(parse_path("a.b"), None);
+        assert_eq!(parse_path("$..a"), None);
+    }
+
+    #[test]
+    fn read_records_works() {
+        let text = r#"
+            {"id": 1, "payload": {"user": {"name": "tom"}}, "tags": ["a", "b"], "score": 1}
+            {"id": 2, "payload": {"user": {"name": "alice"}}, "tags": [], "score": 2.5, "extra": true}
+        "#;
+        let df = read_records(text, None).unwrap();
+        assert_eq!(
+            df.get_column_names(),
+            &["id", "payload", "tags", "score", "extra"]
+        );
+        assert_eq!(df.column("id").unwrap().dtype(), &DataType::Int64);
+        assert_eq!(df.column("payload").unwrap().dtype(), &DataType::Utf8);
+        assert!(matches!(
+            df.column("tags").unwrap().dtype(),
+            DataType::List(_)
+        ));
+        assert_eq!(df.column("score").unwrap().dtype(), &DataType::Float64);
+        assert_eq!(df.column("extra").unwrap().get(0), AnyValue::Null);
+
+        let df = read_records(r#"[{"a": 1}, {"a": null}]"#, None).unwrap();
+        assert_eq!(df.shape(), (2, 1));
+        assert!(read_records("[1, 2]", None).is_err());
+    }
+
+    #[test]
+    fn read_records_stops_after_max_rows() {
+        // 超过上限后就不再解析，后面不合法的内容不会被读到
+        for text in [
+            r#"[{"a": 1}, {"a": 2}, {"a": 3}, oops"#,
+            "{\"a\": 1}\n{\"a\": 2}\n{\"a\": 3}\noops",
+        ] {
+            assert!(matches!(
+                read_records(text, Some(2)),
+                Err(QueryError::LimitExceeded(_))
+            ));
+            assert!(matches!(
+                read_records(text, Some(3)),
+                Err(QueryError::Load(_))
+            ));
+        }
+        let df = read_records(r#"[{"a": 1}, {"a": 2}]"#, Some(2)).unwrap();
+        assert_eq!(df.height(), 2);
+    }
+
+    #[tokio::test]
+    async fn json_source_works() {
+        let file = TempFile::new(
+            ".ndjson",
+            concat!(
+                r#"{"id": 1, "payload": {"user": {"name": "tom", "age": 30}, "items": [{"sku": "a"}, {"sku": "b"}]}, "tags": ["x", "y"], "scores": []}"#,
+                "\n",
+                r#"{"id": 2, "payload": {"user": {"name": "alice", "age": 25}, "items": []}, "tags": ["z"], "scores": [7, 8]}"#,
+                "\n",
+            ),
+        );
+        let mut ctx = Context::new();
+        ctx.register_source("events", file.uri(), LoadOptions::default());
+
+        let sql = "SELECT id, payload->'user'->>'name' AS name, payload->'user' AS user, \
+                   json_extract(payload, '$.items[0].sku') AS sku \
+                   FROM events WHERE CAST(payload->'user'->>'age' AS INT) > 26";
+        let ds = ctx.query(sql).await.unwrap();
+        assert_eq!(ds.height(), 1);
+        assert_eq!(ds.column("name").unwrap().get(0), AnyValue::Utf8("tom"));
+        assert_eq!(
+            ds.column("user").unwrap().get(0),
+            AnyValue::Utf8(r#"{"name":"tom","age":30}"#)
+        );
+        assert_eq!(ds.column("sku").unwrap().get(0), AnyValue::Utf8("a"));
+
+        // 括号里的 -> 和后面的合并成一个路径
+        let sql = "SELECT (payload->'items')->-1->>'sku' AS last FROM events";
+        let ds = ctx.query(sql).await.unwrap();
+        assert_eq!(ds.column("last").unwrap().get(0), AnyValue::Utf8("b"));
+        assert_eq!(ds.column("last").unwrap().get(1), AnyValue::Null);
+
+        // List 列和 JSON 数组都可以展开
+        let sql = "SELECT id, UNNEST(tags) AS tag FROM events ORDER BY tag DESC";
+        let ds = ctx.query(sql).await.unwrap();
+        let tags: Vec<_> = ds
+            .column("tag")
+            .unwrap()
+            .utf8()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(tags, vec![Some("z"), Some("y"), Some("x")]);
+        assert_eq!(ds.column("id").unwrap().get(0), AnyValue::Int64(2));
+
+        // 空的列表不产生行
+        let ds = ctx
+            .query("SELECT id, UNNEST(scores) FROM events")
+            .await
+            .unwrap();
+        assert_eq!(ds.height(), 2);
+        assert_eq!(
+            ds.column("UNNEST(scores)").unwrap().get(1),
+            AnyValue::Int64(8)
+        );
+
+        // 写成 CSV 时 List 列是 JSON 文本
+        let ds = ctx.query("SELECT id, scores FROM events").await.unwrap();
+        assert_eq!(ds.to_csv().unwrap(), "id,scores\n1,[]\n2,\"[7,8]\"\n");
+
+        let sql = "SELECT UNNEST(payload->'items') AS item FROM events";
+        let ds = ctx.query(sql).await.unwrap();
+        assert_eq!(
+            ds.column("item").unwrap().get(1),
+            AnyValue::Utf8(r#"{"sku":"b"}"#)
+        );
+
+        let err = ctx
+            .query("SELECT json_extract(payload, 'items') FROM events")
+            .await;
+        assert!(matches!(err, Err(QueryError::Unsupported { .. })));
+        let err = ctx.query("SELECT id + UNNEST(tags) FROM events").await;
+        assert!(matches!(err, Err(QueryError::Unsupported { .. })));
+    }
+}
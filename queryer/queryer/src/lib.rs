@@ -1,20 +1,43 @@
-use anyhow::{anyhow, Result};
 use polars::prelude::*;
-use sqlparser::parser::Parser;
-use std::convert::TryInto;
 use std::ops::{Deref, DerefMut};
-use tracing::info;
 
+mod cache;
+mod cancel;
+mod context;
 mod convert;
 mod dialect;
+mod error;
 mod fetcher;
+mod json;
+mod limits;
 mod loader;
-use convert::Sql;
-use loader::detect_content;
-use fetcher::retrieve_data;
-
+mod params;
+mod regexp;
+mod reshape;
+mod sample;
+mod sqlite;
+mod stats;
+mod temporal;
+#[cfg(test)]
+mod testing;
+mod udf;
+mod validate;
+mod writer;
+
+pub use cache::CacheOptions;
+pub use cancel::{CancelToken, QueryOptions};
+pub use context::{read_source, Context, Table, SOURCE_FILE_COLUMN};
 pub use dialect::example_sql;
 pub use dialect::TyrDialect;
+pub use error::{QueryError, Result};
+pub use fetcher::{retrieve_data, retrieve_data_limited};
+pub use limits::Limits;
+pub use loader::LoadOptions;
+pub use params::{Param, Params};
+pub use polars::prelude::{AnyValue, DataType};
+pub use stats::QueryStats;
+pub use udf::ScalarFunction;
+pub use writer::Format;
 
 #[derive(Debug)]
 pub struct DataSet(DataFrame);
@@ -33,54 +56,73 @@ impl DerefMut for DataSet {
     }
 }
 
-impl DataSet {
-    pub fn to_csv(&self) -> Result<String> {
-        let mut buf = Vec::new();
-        let writer = CsvWriter::new(&mut buf);
-        writer.finish(self)?;
-        Ok(String::from_utf8(buf)?)
+impl From<DataFrame> for DataSet {
+    fn from(df: DataFrame) -> Self {
+        Self(df)
     }
 }
 
-pub async fn query<T: AsRef<str>>(sql: T) -> Result<DataSet> {
-    let ast = Parser::parse_sql(&TyrDialect::default(), sql.as_ref())?;
-
-    if ast.len() != 1 {
-        return Err(anyhow!("Only support single sql at the moment!"));
+impl DataSet {
+    pub fn to_csv(&self) -> Result<String> {
+        String::from_utf8(self.serialize(Format::Csv)?).map_err(QueryError::execution)
     }
 
-    let sql = &ast[0];
-
-    let Sql {
-        source,
-        condition,
-        selection,
-        offset,
-        limit,
-        order_by,
-    } = sql.try_into()?;
-
-    info!("retrieving data from source: {}", source);
-
-    let ds = detect_content(retrieve_data(source).await?).load()?;
-
-    let mut filtered = match condition {
-        Some(expr) => ds.0.lazy().filter(expr),
-        None => ds.0.lazy(),
-    };
+    /// 按指定格式序列化
+    pub fn serialize(&self, format: Format) -> Result<Vec<u8>> {
+        writer::serialize(self, format)
+    }
 
-    filtered = order_by
-                .into_iter()
-                .fold(filtered, |acc, (col, desc) | acc.sort(&col, desc));
-    
-    if offset.is_some() || limit.is_some() {
-        filtered = filtered.slice(offset.unwrap_or(0), limit.unwrap_or(usize::MAX));
+    /// 列出每一列的列名、类型、空值数量和一个示例值
+    pub fn describe(&self) -> Result<DataSet> {
+        let columns = self.get_columns();
+        let names: Vec<&str> = columns.iter().map(|s| s.name()).collect();
+        let dtypes: Vec<String> = columns.iter().map(|s| format!("{:?}", s.dtype())).collect();
+        let null_counts: Vec<u32> = columns.iter().map(|s| s.null_count() as u32).collect();
+        let samples: Vec<Option<String>> = columns
+            .iter()
+            .map(|s| {
+                (0..s.len())
+                    .map(|i| s.get(i))
+                    .find(|v| !matches!(v, AnyValue::Null))
+                    .map(|v| match v {
+                        AnyValue::Utf8(v) => v.to_string(),
+                        v => v.to_string(),
+                    })
+            })
+            .collect();
+
+        let df = DataFrame::new(vec![
+            Series::new("column", names),
+            Series::new("dtype", dtypes),
+            Series::new("null_count", null_counts),
+            Series::new("sample", samples),
+        ])?;
+        Ok(DataSet(df))
     }
+}
 
-    Ok(DataSet(filtered.select(selection).collect()?))
+pub async fn query<T: AsRef<str>>(sql: T) -> Result<DataSet> {
+    Context::default().query(sql).await
+}
 
+/// 执行带占位符的查询，见 [`Context::query_with_params`]
+pub async fn query_with_params<T: AsRef<str>>(
+    sql: T,
+    params: impl Into<Params>,
+) -> Result<DataSet> {
+    Context::default().query_with_params(sql, params).await
 }
 
+/// 带超时和取消的查询，见 [`Context::query_with_options`]
+pub async fn query_with_options<T: AsRef<str>>(
+    sql: T,
+    params: impl Into<Params>,
+    options: QueryOptions,
+) -> Result<DataSet> {
+    Context::default()
+        .query_with_options(sql, params, options)
+        .await
+}
 
 #[cfg(test)]
 mod tests {
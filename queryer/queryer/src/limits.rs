@@ -0,0 +1,84 @@
+use crate::error::{QueryError, Result};
+
+/// 查询能使用的资源上限，`None` 表示不限制。超过上限时尽早报错，而不是把内存用光
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Limits {
+    /// 每个数据源最多下载或者读取多少字节
+    pub max_fetch_bytes: Option<u64>,
+    /// 每个数据源最多加载多少行，注册数据源时声明的 `LoadOptions::max_rows` 更小时用它
+    pub max_rows_loaded: Option<usize>,
+    /// 查询结果最多多少行
+    pub max_result_rows: Option<usize>,
+}
+
+impl Limits {
+    pub fn with_max_fetch_bytes(mut self, bytes: u64) -> Self {
+        self.max_fetch_bytes = Some(bytes);
+        self
+    }
+
+    pub fn with_max_rows_loaded(mut self, rows: usize) -> Self {
+        self.max_rows_loaded = Some(rows);
+        self
+    }
+
+    pub fn with_max_result_rows(mut self, rows: usize) -> Self {
+        self.max_result_rows = Some(rows);
+        self
+    }
+}
+
+/// 检查数据源的大小，超过上限时报错
+pub(crate) fn check_bytes(uri: &str, bytes: u64, max: Option<u64>) -> Result<()> {
+    match max {
+        Some(max) if bytes > max => Err(QueryError::LimitExceeded(format!(
+            "{} is larger than the limit of {} bytes",
+            uri, max
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// 检查行数，what 说明是加载的行还是结果的行
+pub(crate) fn check_rows(what: &str, rows: usize, max: Option<usize>) -> Result<()> {
+    match max {
+        Some(max) if rows > max => Err(QueryError::LimitExceeded(format!(
+            "{} has more than {} rows",
+            what, max
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempFile;
+    use crate::{Context, DataSet, LoadOptions};
+
+    #[tokio::test]
+    async fn limits_are_enforced() {
+        let rows: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let file = TempFile::new(".csv", format!("value\n{}\n", rows.join("\n")));
+        let uri = file.uri();
+        let mut ctx = Context::new();
+        ctx.register_source("numbers", &uri, LoadOptions::default());
+        let limit_exceeded = |r: Result<DataSet>| matches!(r, Err(QueryError::LimitExceeded(_)));
+
+        ctx.set_limits(Limits::default().with_max_fetch_bytes(10));
+        assert!(limit_exceeded(ctx.query("SELECT * FROM numbers").await));
+
+        ctx.set_limits(Limits::default().with_max_rows_loaded(10));
+        assert!(limit_exceeded(ctx.query("SELECT * FROM numbers").await));
+        // 数据源自己声明的上限更小时用它
+        ctx.register_source("small", &uri, LoadOptions::default().with_max_rows(5));
+        ctx.set_limits(Limits::default().with_max_rows_loaded(30));
+        assert!(limit_exceeded(ctx.query("SELECT * FROM small").await));
+        assert!(ctx.query("SELECT * FROM numbers").await.is_ok());
+
+        ctx.set_limits(Limits::default().with_max_result_rows(5));
+        assert!(limit_exceeded(ctx.query("SELECT * FROM numbers").await));
+        let ds = ctx.query("SELECT * FROM numbers LIMIT 5").await.unwrap();
+        assert_eq!(ds.height(), 5);
+    }
+}
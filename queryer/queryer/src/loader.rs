@@ -1,42 +1,627 @@
+use crate::error::{QueryError, Result};
+use crate::json::read_records;
+use crate::limits::check_rows;
+use crate::temporal::{detect_temporal, parse_temporal};
 use crate::DataSet;
-use anyhow::Result;
+use calamine::{open_workbook_auto_from_rs, Data, ExcelDateTime, ExcelDateTimeType, Reader};
 use polars::prelude::*;
 use std::io::Cursor;
 
+/// 默认用多少行数据推断列类型
+pub const DEFAULT_INFER_SCHEMA_LENGTH: usize = 16;
+
 pub trait Load {
     type Error;
-    fn load(self) -> Result<DataSet, Self::Error>;
+    fn load(self, options: &LoadOptions) -> Result<DataSet, Self::Error>;
+}
+
+/// 加载数据源时的选项
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadOptions {
+    /// 显式声明的列类型，没有声明的列仍然通过推断得到类型
+    pub schema: Vec<(String, DataType)>,
+    /// 用多少行数据推断列类型，`None` 表示读取整个文件来推断
+    pub infer_schema_length: Option<usize>,
+    /// 是否把内容都是日期或时间的字符串列自动转换成 Date32 / Date64。
+    /// 默认关闭，免得已有的字符串列变了类型；电子表格里日期格式的单元格不受影响，总是日期
+    pub parse_dates: bool,
+    /// 读取电子表格时用哪个工作表，`None` 表示第一个
+    pub sheet: Option<String>,
+    /// 电子表格里哪一行是表头，从有数据的第一行开始数，上面的行会被跳过。
+    /// `None` 表示没有表头，列名是 column_1、column_2……
+    pub header_row: Option<usize>,
+    /// 最多加载多少行，超过时报错
+    pub max_rows: Option<usize>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            schema: Vec::new(),
+            infer_schema_length: Some(DEFAULT_INFER_SCHEMA_LENGTH),
+            parse_dates: false,
+            sheet: None,
+            header_row: Some(0),
+            max_rows: None,
+        }
+    }
+}
+
+impl LoadOptions {
+    /// 声明列名和列类型
+    pub fn with_schema<S, I>(mut self, schema: I) -> Self
+    where
+        S: Into<String>,
+        I: IntoIterator<Item = (S, DataType)>,
+    {
+        self.schema = schema.into_iter().map(|(n, t)| (n.into(), t)).collect();
+        self
+    }
+
+    /// 设置用多少行数据推断列类型，`None` 表示读取整个文件来推断
+    pub fn with_infer_schema_length(mut self, length: Option<usize>) -> Self {
+        self.infer_schema_length = length;
+        self
+    }
+
+    /// 读取整个文件来推断列类型
+    pub fn infer_full_schema(self) -> Self {
+        self.with_infer_schema_length(None)
+    }
+
+    /// 设置是否自动识别日期和时间列
+    pub fn with_parse_dates(mut self, parse_dates: bool) -> Self {
+        self.parse_dates = parse_dates;
+        self
+    }
+
+    /// 选择电子表格的工作表
+    pub fn with_sheet(mut self, sheet: impl Into<String>) -> Self {
+        self.sheet = Some(sheet.into());
+        self
+    }
+
+    /// 设置电子表格的表头在哪一行，`None` 表示没有表头
+    pub fn with_header_row(mut self, header_row: Option<usize>) -> Self {
+        self.header_row = header_row;
+        self
+    }
+
+    /// 设置最多加载多少行
+    pub fn with_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
 }
 
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum Loader {
     Csv(CsvLoader),
+    Spreadsheet(SpreadsheetLoader),
+    Json(JsonLoader),
 }
 
 #[derive(Debug, Default)]
 pub struct CsvLoader(pub(crate) String);
 
+/// JSON 数组或者每行一个对象的 NDJSON
+#[derive(Debug, Default)]
+pub struct JsonLoader(pub(crate) String);
+
+/// xlsx、xlsm、xlsb、xls 和 ods 文件
+#[derive(Debug, Default)]
+pub struct SpreadsheetLoader(pub(crate) Vec<u8>);
+
 impl Loader {
-    pub fn load(self) -> Result<DataSet> {
+    pub fn load(self, options: &LoadOptions) -> Result<DataSet> {
         match self {
-            Loader::Csv(csv) => csv.load(),
+            Loader::Csv(csv) => csv.load(options),
+            Loader::Spreadsheet(spreadsheet) => spreadsheet.load(options),
+            Loader::Json(json) => json.load(options),
         }
     }
 }
 
-pub fn detect_content(data: String) -> Loader {
-
+/// 根据文件头判断格式：zip（xlsx、ods）和 OLE（xls）是电子表格，`{` 或 `[` 开头的是 JSON，
+/// 其它的当成 CSV
+pub fn detect_content(data: impl Into<Vec<u8>>) -> Loader {
+    let data = data.into();
+    if data.starts_with(b"PK\x03\x04") || data.starts_with(&[0xd0, 0xcf, 0x11, 0xe0]) {
+        return Loader::Spreadsheet(SpreadsheetLoader(data));
+    }
+    let data = to_text(data);
+    if data.trim_start().starts_with(['{', '[']) {
+        return Loader::Json(JsonLoader(data));
+    }
     Loader::Csv(CsvLoader(data))
 }
 
+fn to_text(data: Vec<u8>) -> String {
+    String::from_utf8(data).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+/// 先根据地址的扩展名判断格式，没有认识的扩展名时再看文件头
+pub fn detect_source(uri: &str, data: impl Into<Vec<u8>>) -> Loader {
+    let path = uri.split(['?', '#']).next().unwrap_or_default();
+    let extension = path.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("xlsx" | "xlsm" | "xlsb" | "xls" | "ods") => {
+            Loader::Spreadsheet(SpreadsheetLoader(data.into()))
+        }
+        Some("json" | "ndjson" | "jsonl") => Loader::Json(JsonLoader(to_text(data.into()))),
+        _ => detect_content(data),
+    }
+}
+
 impl Load for CsvLoader {
-    type Error = anyhow::Error;
+    type Error = QueryError;
+
+    fn load(self, options: &LoadOptions) -> Result<DataSet, Self::Error> {
+        let declared = utf8_schema(options.schema.iter().map(|(name, _)| name.as_str()));
+        let result = CsvReader::new(Cursor::new(self.0.as_bytes()))
+            .infer_schema(options.infer_schema_length)
+            .with_dtypes(Some(&declared))
+            // 多读一行，用来判断是不是超过了上限
+            .with_stop_after_n_rows(options.max_rows.map(|n| n + 1))
+            .finish();
+
+        let df = match result {
+            Ok(df) => df,
+            // 推断出来的类型和后面的数据对不上，找出具体是哪一行哪一列
+            Err(e) => {
+                return Err(locate_inference_error(&self.0, options)
+                    .unwrap_or_else(|| QueryError::Load(e.to_string())))
+            }
+        };
+
+        check_rows("source", df.height(), options.max_rows)?;
+
+        let mut df = apply_schema(df, &options.schema)?;
+        if options.parse_dates {
+            df = parse_dates(df, &options.schema)?;
+        }
+        Ok(DataSet(df))
+    }
+}
+
+impl Load for JsonLoader {
+    type Error = QueryError;
+
+    fn load(self, options: &LoadOptions) -> Result<DataSet, Self::Error> {
+        let df = read_records(&self.0, options.max_rows)?;
+
+        let mut df = apply_schema(df, &options.schema)?;
+        if options.parse_dates {
+            df = parse_dates(df, &options.schema)?;
+        }
+        Ok(DataSet(df))
+    }
+}
+
+impl Load for SpreadsheetLoader {
+    type Error = QueryError;
+
+    fn load(self, options: &LoadOptions) -> Result<DataSet, Self::Error> {
+        let error = |e: calamine::Error| QueryError::Load(e.to_string());
+        let mut workbook = open_workbook_auto_from_rs(Cursor::new(self.0)).map_err(error)?;
+        let sheets = workbook.sheet_names();
+        let sheet = match &options.sheet {
+            Some(sheet) if sheets.contains(sheet) => sheet.clone(),
+            Some(sheet) => {
+                return Err(QueryError::Load(format!(
+                    "sheet `{}` not found, available sheets: {}",
+                    sheet,
+                    sheets.join(", ")
+                )))
+            }
+            None => sheets
+                .first()
+                .cloned()
+                .ok_or_else(|| QueryError::Load("workbook has no sheet".into()))?,
+        };
+        let range = workbook.worksheet_range(&sheet).map_err(error)?;
+
+        let mut all_rows = range.rows();
+        let names: Vec<String> = match options.header_row {
+            Some(n) => match all_rows.nth(n) {
+                Some(header) => header
+                    .iter()
+                    .enumerate()
+                    .map(|(i, cell)| match cell_text(cell) {
+                        Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+                        _ => format!("column_{}", i + 1),
+                    })
+                    .collect(),
+                None => {
+                    return Err(QueryError::Load(format!(
+                        "header row {} is beyond the last row of sheet `{}`",
+                        n, sheet
+                    )))
+                }
+            },
+            None => (1..=range.width())
+                .map(|i| format!("column_{}", i))
+                .collect(),
+        };
+        // 跳过整行都是空的行，超过上限时立即报错，不再处理后面的行
+        let mut rows: Vec<&[Data]> = Vec::new();
+        for row in all_rows.filter(|row| row.iter().any(|cell| cell_text(cell).is_some())) {
+            rows.push(row);
+            check_rows(&format!("sheet `{}`", sheet), rows.len(), options.max_rows)?;
+        }
 
-    fn load(self) -> Result<DataSet, Self::Error> {
-        let df = CsvReader::new(Cursor::new(self.0))
-            .infer_schema(Some(16))
-            .finish()?;
+        let columns: Vec<Series> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let cells: Vec<&Data> = rows.iter().map(|row| &row[i]).collect();
+                cells_to_series(name, &cells)
+            })
+            .collect();
+        let df = DataFrame::new(columns).map_err(|e| QueryError::Load(e.to_string()))?;
+
+        let mut df = apply_schema(df, &options.schema)?;
+        if options.parse_dates {
+            df = parse_dates(df, &options.schema)?;
+        }
         Ok(DataSet(df))
     }
-}
\ No newline at end of file
+}
+
+/// 按单元格的值决定列类型：都是整数时是 Int64，都是数字时是 Float64，都是布尔值时是 Boolean，
+/// 都是日期时是 Date32 / Date64，其它情况是 Utf8
+fn cells_to_series(name: &str, cells: &[&Data]) -> Series {
+    let values = || cells.iter().filter(|cell| cell_text(cell).is_some());
+    let integral = |v: f64| v.fract() == 0.0 && v.abs() < i64::MAX as f64;
+
+    if values().next().is_none() {
+        return Series::new(name, vec![None::<&str>; cells.len()]);
+    }
+    if values().all(|cell| matches!(cell, Data::Bool(_))) {
+        let values: Vec<_> = cells
+            .iter()
+            .map(|cell| match cell {
+                Data::Bool(v) => Some(*v),
+                _ => None,
+            })
+            .collect();
+        return Series::new(name, values);
+    }
+    if values()
+        .all(|cell| matches!(cell, Data::Int(_)) || matches!(cell, Data::Float(v) if integral(*v)))
+    {
+        let values: Vec<_> = cells
+            .iter()
+            .map(|cell| match cell {
+                Data::Int(v) => Some(*v),
+                Data::Float(v) => Some(*v as i64),
+                _ => None,
+            })
+            .collect();
+        return Series::new(name, values);
+    }
+    if values().all(|cell| matches!(cell, Data::Int(_) | Data::Float(_))) {
+        let values: Vec<_> = cells
+            .iter()
+            .map(|cell| match cell {
+                Data::Int(v) => Some(*v as f64),
+                Data::Float(v) => Some(*v),
+                _ => None,
+            })
+            .collect();
+        return Series::new(name, values);
+    }
+
+    let dates = values().all(|cell| matches!(cell, Data::DateTime(_) | Data::DateTimeIso(_)));
+    let texts: Vec<_> = cells.iter().map(|cell| cell_text(cell)).collect();
+    let s = Series::new(name, texts);
+    // 日期先转换成了字符串，再解析回来
+    match dates {
+        true => detect_temporal(&s).unwrap_or(s),
+        false => s,
+    }
+}
+
+/// 单元格的文本，空单元格和出错的单元格（比如 `#N/A`）返回 None
+fn cell_text(cell: &Data) -> Option<String> {
+    match cell {
+        Data::Empty | Data::Error(_) => None,
+        Data::String(v) | Data::DateTimeIso(v) | Data::DurationIso(v) => Some(v.clone()),
+        Data::DateTime(v) => {
+            // 不开 calamine 的 dates 功能时拿不到日期的类型，只能比较出来
+            let days = v.as_f64();
+            let is = |kind, is_1904| *v == ExcelDateTime::new(days, kind, is_1904);
+            if is(ExcelDateTimeType::TimeDelta, false) || is(ExcelDateTimeType::TimeDelta, true) {
+                Some(days.to_string())
+            } else if is(ExcelDateTimeType::DateTime, true) {
+                // 1904 日期系统从 1904-01-01 开始
+                Some(excel_datetime(days + 1462.0))
+            } else {
+                Some(excel_datetime(days))
+            }
+        }
+        Data::Int(v) => Some(v.to_string()),
+        Data::Float(v) => Some(v.to_string()),
+        Data::Bool(v) => Some(v.to_string()),
+    }
+}
+
+/// Excel 的日期时间是从 1899-12-30 开始的天数，小数部分是一天里的时间
+fn excel_datetime(days: f64) -> String {
+    let millis = (days * 86_400_000.0).round() as i64;
+    let v = chrono::NaiveDate::from_ymd(1899, 12, 30).and_hms(0, 0, 0)
+        + chrono::Duration::milliseconds(millis);
+    if millis % 86_400_000 == 0 {
+        v.date().to_string()
+    } else {
+        v.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+}
+
+/// 把没有声明类型、内容都是日期或时间的字符串列转换成 Date32 / Date64
+pub(crate) fn parse_dates(mut df: DataFrame, schema: &[(String, DataType)]) -> Result<DataFrame> {
+    let detected: Vec<Series> = df
+        .get_columns()
+        .iter()
+        .filter(|s| !schema.iter().any(|(name, _)| name == s.name()))
+        .filter_map(detect_temporal)
+        .collect();
+
+    for s in detected {
+        let name = s.name().to_string();
+        df.replace(&name, s)?;
+    }
+    Ok(df)
+}
+
+/// 把给定的列都声明成 Utf8，先按字符串读进来，再按声明的类型逐列转换
+fn utf8_schema<'a>(names: impl Iterator<Item = &'a str>) -> Schema {
+    Schema::new(names.map(|name| Field::new(name, DataType::Utf8)).collect())
+}
+
+/// 把 Utf8 的列转换成声明的类型，转换失败时报告出错的行和列
+pub(crate) fn apply_schema(mut df: DataFrame, schema: &[(String, DataType)]) -> Result<DataFrame> {
+    for (name, dtype) in schema {
+        let raw = df.column(name).map_err(|_| {
+            QueryError::Load(format!(
+                "column `{}` declared in schema is not in the source",
+                name
+            ))
+        })?;
+        let typed = match dtype {
+            DataType::Date32 | DataType::Date64 => parse_temporal(raw, dtype)?,
+            _ => raw
+                .cast_with_dtype(dtype)
+                .map_err(|e| QueryError::Load(e.to_string()))?,
+        };
+
+        if typed.null_count() != raw.null_count() {
+            // 转换失败的值会变成 null，找到第一个原本非空但转换后为空的值
+            let row = raw
+                .is_null()
+                .into_iter()
+                .zip(&typed.is_null())
+                .position(|(before, after)| before == Some(false) && after == Some(true))
+                .unwrap_or_default();
+            return Err(QueryError::Load(format!(
+                "schema mismatch at row {}, column `{}`: cannot parse {} as {:?}",
+                row + 1,
+                name,
+                raw.get(row),
+                dtype
+            )));
+        }
+
+        df.replace(name, typed)
+            .map_err(|e| QueryError::Load(e.to_string()))?;
+    }
+
+    Ok(df)
+}
+
+/// 推断失败时，用前 N 行推断出的类型重新校验所有数据，返回带行列信息的错误
+fn locate_inference_error(data: &str, options: &LoadOptions) -> Option<QueryError> {
+    let length = options.infer_schema_length?;
+    let declared = utf8_schema(options.schema.iter().map(|(name, _)| name.as_str()));
+    // 只取表头和前 N 行，得到推断出来的类型
+    let head = data.lines().take(length + 1).collect::<Vec<_>>().join("\n");
+    let sample = CsvReader::new(Cursor::new(head))
+        .infer_schema(Some(length))
+        .with_dtypes(Some(&declared))
+        .finish()
+        .ok()?;
+
+    let inferred: Vec<_> = sample
+        .schema()
+        .fields()
+        .iter()
+        .filter(|f| f.data_type() != &DataType::Utf8)
+        .map(|f| (f.name().to_string(), f.data_type().clone()))
+        .collect();
+
+    let raw = CsvReader::new(Cursor::new(data.as_bytes()))
+        .with_dtypes(Some(&utf8_schema(sample.get_column_names().into_iter())))
+        .finish()
+        .ok()?;
+
+    apply_schema(raw, &inferred).err().map(|e| {
+        QueryError::Load(format!(
+            "{} (types were inferred from the first {} rows, declare a schema or infer from the whole file)",
+            e,
+            length
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::numbers;
+
+    #[test]
+    fn declared_schema_works() {
+        let options = LoadOptions::default().with_schema(vec![("value", DataType::Float64)]);
+        let ds = detect_content(numbers()).load(&options).unwrap();
+        assert_eq!(ds.column("value").unwrap().dtype(), &DataType::Float64);
+        assert_eq!(ds.height(), 21);
+    }
+
+    #[test]
+    fn full_schema_inference_works() {
+        let options = LoadOptions::default().infer_full_schema();
+        let ds = detect_content(numbers()).load(&options).unwrap();
+        assert_eq!(ds.column("value").unwrap().dtype(), &DataType::Float64);
+    }
+
+    #[test]
+    fn inference_mismatch_reports_row_and_column() {
+        let err = detect_content(numbers())
+            .load(&LoadOptions::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("row 21"), "{}", err);
+        assert!(err.contains("`value`"), "{}", err);
+    }
+
+    #[test]
+    fn schema_mismatch_reports_row_and_column() {
+        let data = "name,value\na,1\nb,x\n".to_string();
+        let options = LoadOptions::default().with_schema(vec![("value", DataType::Int64)]);
+        let err = detect_content(data).load(&options).unwrap_err().to_string();
+        assert!(err.contains("row 2"), "{}", err);
+        assert!(err.contains("`value`"), "{}", err);
+    }
+
+    #[test]
+    fn date_columns_are_parsed() {
+        let data =
+            "day,at,name\n2021-01-01,2021-01-01 10:00:00,a\n2021-01-02,2021-01-02 11:00:00,b\n";
+        let options = LoadOptions::default().with_parse_dates(true);
+        let ds = detect_content(data.to_string()).load(&options).unwrap();
+        assert_eq!(ds.column("day").unwrap().dtype(), &DataType::Date32);
+        assert_eq!(ds.column("at").unwrap().dtype(), &DataType::Date64);
+        assert_eq!(ds.column("name").unwrap().dtype(), &DataType::Utf8);
+
+        // 默认不识别，字符串列保持原样
+        let ds = detect_content(data.to_string())
+            .load(&LoadOptions::default())
+            .unwrap();
+        assert_eq!(ds.column("day").unwrap().dtype(), &DataType::Utf8);
+        assert_eq!(ds.column("at").unwrap().dtype(), &DataType::Utf8);
+
+        let data = "day\n2021-01-01\n2021-13-01\n".to_string();
+        let options = LoadOptions::default().with_schema(vec![("day", DataType::Date32)]);
+        let err = detect_content(data).load(&options).unwrap_err().to_string();
+        assert!(err.contains("row 2"), "{}", err);
+    }
+
+    /// 两个工作表：people 的第一行是表头，report 的第一行是标题，第二行才是表头
+    fn workbook() -> Vec<u8> {
+        use rust_xlsxwriter::{ExcelDateTime, Format, Workbook};
+
+        let mut workbook = Workbook::new();
+        let date = Format::new().set_num_format("yyyy-mm-dd");
+        let people = workbook.add_worksheet().set_name("people").unwrap();
+        people
+            .write_row(0, 0, ["name", "age", "score", "active", "joined"])
+            .unwrap();
+        for (i, &(name, age, score, active)) in [("alice", 30, 1.5, true), ("bob", 41, 2.0, false)]
+            .iter()
+            .enumerate()
+        {
+            let row = i as u32 + 1;
+            people.write(row, 0, name).unwrap();
+            people.write(row, 1, age).unwrap();
+            people.write(row, 2, score).unwrap();
+            people.write(row, 3, active).unwrap();
+            let joined = ExcelDateTime::from_ymd(2021, 1, row as u8).unwrap();
+            people
+                .write_datetime_with_format(row, 4, &joined, &date)
+                .unwrap();
+        }
+
+        let report = workbook.add_worksheet().set_name("report").unwrap();
+        report.write(0, 0, "Quarterly report").unwrap();
+        report.write_row(1, 0, ["region", "total"]).unwrap();
+        report.write_row(2, 0, ["north", "12"]).unwrap();
+        report.write(2, 1, 12).unwrap();
+        workbook.save_to_buffer().unwrap()
+    }
+
+    #[test]
+    fn json_loader_works() {
+        let data = "[{\"name\": \"a\", \"day\": \"2021-01-01\"}, {\"name\": \"b\"}]";
+        assert!(matches!(detect_content(data), Loader::Json(_)));
+        assert!(matches!(
+            detect_source("file:///tmp/a.jsonl", "{}"),
+            Loader::Json(_)
+        ));
+
+        let ds = detect_content(data).load(&LoadOptions::default()).unwrap();
+        assert_eq!(ds.shape(), (2, 2));
+        assert_eq!(ds.column("day").unwrap().dtype(), &DataType::Utf8);
+        let options = LoadOptions::default().with_parse_dates(true);
+        let ds = detect_content(data).load(&options).unwrap();
+        assert_eq!(ds.column("day").unwrap().dtype(), &DataType::Date32);
+
+        let options = LoadOptions::default().with_max_rows(1);
+        assert!(matches!(
+            detect_content(data).load(&options),
+            Err(QueryError::LimitExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn spreadsheet_loader_works() {
+        let data = workbook();
+        assert!(matches!(
+            detect_content(data.clone()),
+            Loader::Spreadsheet(_)
+        ));
+        assert!(matches!(
+            detect_source("file:///tmp/a.ods?x=1", "a,b\n1,2"),
+            Loader::Spreadsheet(_)
+        ));
+
+        let ds = detect_content(data.clone())
+            .load(&LoadOptions::default())
+            .unwrap();
+        assert_eq!(ds.shape(), (2, 5));
+        assert_eq!(ds.column("age").unwrap().dtype(), &DataType::Int64);
+        assert_eq!(ds.column("score").unwrap().dtype(), &DataType::Float64);
+        assert_eq!(ds.column("active").unwrap().dtype(), &DataType::Boolean);
+        assert_eq!(ds.column("joined").unwrap().dtype(), &DataType::Date32);
+        assert_eq!(ds.column("name").unwrap().get(1), AnyValue::Utf8("bob"));
+
+        let options = LoadOptions::default()
+            .with_sheet("report")
+            .with_header_row(Some(1));
+        let ds = detect_content(data.clone()).load(&options).unwrap();
+        assert_eq!(ds.get_column_names(), vec!["region", "total"]);
+        assert_eq!(ds.column("total").unwrap().get(0), AnyValue::Int64(12));
+
+        let options = LoadOptions::default()
+            .with_sheet("report")
+            .with_header_row(None);
+        let ds = detect_content(data.clone()).load(&options).unwrap();
+        assert_eq!(ds.get_column_names(), vec!["column_1", "column_2"]);
+        assert_eq!(ds.height(), 3);
+
+        let options = LoadOptions::default().with_max_rows(1);
+        let err = detect_content(data.clone()).load(&options).unwrap_err();
+        assert!(matches!(err, QueryError::LimitExceeded(_)), "{}", err);
+        let options = LoadOptions::default().with_max_rows(2);
+        assert_eq!(
+            detect_content(data.clone())
+                .load(&options)
+                .unwrap()
+                .height(),
+            2
+        );
+
+        let options = LoadOptions::default().with_sheet("missing");
+        let err = detect_content(data).load(&options).unwrap_err().to_string();
+        assert!(err.contains("people, report"), "{}", err);
+    }
+}
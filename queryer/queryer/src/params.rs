@@ -0,0 +1,251 @@
+use crate::error::{QueryError, Result};
+use sqlparser::ast::{
+    visit_expressions_mut, CastKind, DataType, Expr as SqlExpr, Statement, Value as SqlValue,
+    ValueWithSpan,
+};
+use sqlparser::tokenizer::{Location, Span};
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+
+/// 绑定到占位符上的值
+#[derive(Debug, Clone, PartialEq)]
+pub enum Param {
+    Null,
+    Boolean(bool),
+    Int64(i64),
+    Float64(f64),
+    Utf8(String),
+}
+
+macro_rules! impl_from_param {
+    ($variant:ident, $($t:ty),+) => {
+        $(
+            impl From<$t> for Param {
+                fn from(v: $t) -> Self {
+                    Param::$variant(v.into())
+                }
+            }
+        )+
+    };
+}
+
+impl_from_param!(Boolean, bool);
+impl_from_param!(Int64, i8, i16, i32, i64, u8, u16, u32);
+impl_from_param!(Float64, f32, f64);
+impl_from_param!(Utf8, &str, String);
+
+impl<T: Into<Param>> From<Option<T>> for Param {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Param::Null, Into::into)
+    }
+}
+
+/// 查询参数。位置参数对应 `$1` / `?1` / `?`，命名参数对应 `:name`。
+/// 多个 `?` 按它们在 SQL 里出现的顺序依次取位置参数
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Params {
+    positional: Vec<Param>,
+    named: HashMap<String, Param>,
+}
+
+impl Params {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 追加一个位置参数
+    pub fn push(mut self, value: impl Into<Param>) -> Self {
+        self.positional.push(value.into());
+        self
+    }
+
+    /// 绑定一个命名参数，name 不带前面的 `:`
+    pub fn bind(mut self, name: impl Into<String>, value: impl Into<Param>) -> Self {
+        self.named.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positional.is_empty() && self.named.is_empty()
+    }
+
+    fn positional(&self, index: usize, placeholder: &str) -> Result<&Param> {
+        self.positional.get(index).ok_or_else(|| {
+            QueryError::Param(format!("No value bound to placeholder {}", placeholder))
+        })
+    }
+
+    fn named(&self, name: &str, placeholder: &str) -> Result<&Param> {
+        self.named.get(name).ok_or_else(|| {
+            QueryError::Param(format!("No value bound to placeholder {}", placeholder))
+        })
+    }
+
+    /// 找到占位符对应的参数，anonymous 是按出现顺序排好的 `?` 的位置
+    fn resolve(&self, placeholder: &str, anonymous: usize) -> Result<&Param> {
+        if placeholder == "?" {
+            return self.positional(anonymous, placeholder);
+        }
+
+        if let Some(name) = placeholder.strip_prefix(':') {
+            return self.named(name, placeholder);
+        }
+
+        let index: usize = placeholder[1..]
+            .parse()
+            .map_err(|_| QueryError::Param(format!("Invalid placeholder {}", placeholder)))?;
+        match index {
+            0 => Err(QueryError::Param(format!(
+                "Placeholder index starts from 1, got {}",
+                placeholder
+            ))),
+            i => self.positional(i - 1, placeholder),
+        }
+    }
+}
+
+impl<T: Into<Param>> From<Vec<T>> for Params {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().fold(Params::new(), Params::push)
+    }
+}
+
+impl From<Param> for SqlValue {
+    fn from(p: Param) -> Self {
+        match p {
+            Param::Null => SqlValue::Null,
+            Param::Boolean(v) => SqlValue::Boolean(v),
+            Param::Int64(v) => SqlValue::Number(v.to_string(), false),
+            Param::Float64(v) => SqlValue::Number(format!("{:?}", v), false),
+            Param::Utf8(v) => SqlValue::SingleQuotedString(v),
+        }
+    }
+}
+
+/// 占位符替换成的表达式。SQL 里的数字常量按 Float64 计算，
+/// 整数参数包一层 `CAST(n AS BIGINT)`，保持 Int64
+fn bound(param: &Param, span: Span) -> SqlExpr {
+    let value = SqlExpr::Value(ValueWithSpan {
+        value: param.clone().into(),
+        span,
+    });
+    match param {
+        Param::Int64(_) => SqlExpr::Cast {
+            kind: CastKind::Cast,
+            expr: Box::new(value),
+            data_type: DataType::BigInt(None),
+            format: None,
+        },
+        _ => value,
+    }
+}
+
+/// 把语句里的占位符替换成绑定的值。替换发生在 AST 上，字符串参数不会被当成 SQL 解析
+pub fn bind_params(statement: &mut Statement, params: &Params) -> Result<()> {
+    // 先找到所有 `?` 的位置，按出现顺序编号
+    let mut anonymous: Vec<Location> = Vec::new();
+    let _ = visit_expressions_mut(statement, |expr| {
+        if let SqlExpr::Value(v) = expr {
+            if matches!(&v.value, SqlValue::Placeholder(p) if p == "?") {
+                anonymous.push(v.span.start);
+            }
+        }
+        ControlFlow::<()>::Continue(())
+    });
+    anonymous.sort();
+
+    let result = visit_expressions_mut(statement, |expr| {
+        let bound = match expr {
+            SqlExpr::Value(ValueWithSpan {
+                value: SqlValue::Placeholder(p),
+                span,
+            }) => {
+                let position = anonymous.binary_search(&span.start).unwrap_or_default();
+                match params.resolve(p, position) {
+                    Ok(param) => bound(param, *span),
+                    Err(e) => return ControlFlow::Break(e),
+                }
+            }
+            _ => return ControlFlow::Continue(()),
+        };
+        *expr = bound;
+        ControlFlow::Continue(())
+    });
+
+    match result {
+        ControlFlow::Break(e) => Err(e),
+        ControlFlow::Continue(()) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::parse_sql;
+    use crate::testing::numbers_context;
+    use polars::prelude::AnyValue;
+
+    fn bind(sql: &str, params: Params) -> Result<String> {
+        let mut statement = parse_sql(sql).unwrap().remove(0);
+        bind_params(&mut statement, &params)?;
+        Ok(statement.to_string())
+    }
+
+    #[test]
+    fn bind_params_works() {
+        let params = Params::new().push(10).push("O'Neil").bind("flag", true);
+        let sql = bind(
+            "SELECT a FROM t WHERE b > $1 AND c = $2 AND d = :flag LIMIT $1",
+            params,
+        )
+        .unwrap();
+        assert_eq!(
+            sql,
+            "SELECT a FROM t WHERE b > CAST(10 AS BIGINT) AND c = 'O''Neil' AND d = true LIMIT CAST(10 AS BIGINT)"
+        );
+
+        let sql = bind(
+            "SELECT a FROM t WHERE b > ? AND c < ?",
+            vec![1.5, 2.0].into(),
+        )
+        .unwrap();
+        assert_eq!(sql, "SELECT a FROM t WHERE b > 1.5 AND c < 2.0");
+    }
+
+    #[test]
+    fn missing_param_should_fail() {
+        let err = bind("SELECT a FROM t WHERE b > $2", vec![1].into()).unwrap_err();
+        assert_eq!(err.to_string(), "No value bound to placeholder $2");
+        let err = bind("SELECT a FROM t WHERE b > :x", Params::new()).unwrap_err();
+        assert_eq!(err.to_string(), "No value bound to placeholder :x");
+    }
+
+    #[tokio::test]
+    async fn query_with_params_works() {
+        let (ctx, _file) = numbers_context();
+
+        let sql = "SELECT name, value FROM numbers WHERE value >= $1 AND name <> :name LIMIT $2";
+        let params = Params::new().push(10).push(3).bind("name", "row11");
+        let ds = ctx.query_with_params(sql, params).await.unwrap();
+        assert_eq!(ds.height(), 3);
+        assert_eq!(ds.column("name").unwrap().get(1), AnyValue::Utf8("row12"));
+
+        let sql = "SELECT name FROM numbers WHERE name = ?";
+        let ds = ctx
+            .query_with_params(sql, vec!["x' OR 1=1 --"])
+            .await
+            .unwrap();
+        assert_eq!(ds.height(), 0);
+    }
+
+    #[tokio::test]
+    async fn integer_params_keep_int64() {
+        let (ctx, _file) = numbers_context();
+        // 数字常量按 Float64 计算，`1 / 2` 不是整数除法；绑定的整数参数仍然是 Int64
+        let sql = "SELECT name, 1 / 2 AS half, $1 AS n FROM numbers WHERE value >= 1 / 2 LIMIT 1";
+        let ds = ctx.query_with_params(sql, vec![2]).await.unwrap();
+        assert_eq!(ds.column("name").unwrap().get(0), AnyValue::Utf8("row1"));
+        assert_eq!(ds.column("half").unwrap().get(0), AnyValue::Float64(0.5));
+        assert_eq!(ds.column("n").unwrap().get(0), AnyValue::Int64(2));
+    }
+}
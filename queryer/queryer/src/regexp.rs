@@ -0,0 +1,229 @@
+use crate::error::{QueryError, Result};
+use polars::prelude::*;
+use regex::Regex;
+use sqlparser::tokenizer::Span;
+
+/// 正则函数的选项，`i` 不区分大小写，`g` 替换所有匹配的地方
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags {
+    pub case_insensitive: bool,
+    pub global: bool,
+}
+
+impl Flags {
+    pub fn parse(flags: &str) -> Option<Self> {
+        let mut parsed = Self::default();
+        for c in flags.chars() {
+            match c {
+                'i' => parsed.case_insensitive = true,
+                'g' => parsed.global = true,
+                _ => return None,
+            }
+        }
+        Some(parsed)
+    }
+}
+
+/// 编译正则表达式，写错时返回 `QueryError::InvalidRegex`，不用等到执行时才发现
+pub fn compile(pattern: &str, flags: Flags, span: Span) -> Result<Regex> {
+    let full = match flags.case_insensitive {
+        true => format!("(?i){}", pattern),
+        false => pattern.to_string(),
+    };
+    Regex::new(&full).map_err(|e| QueryError::InvalidRegex {
+        pattern: pattern.to_string(),
+        message: e.to_string(),
+        span,
+    })
+}
+
+/// 是否匹配，对应 `~`、`~*` 和 `REGEXP_MATCHES`
+pub fn matches(expr: Expr, re: Regex) -> Expr {
+    expr.cast(DataType::Utf8).map(
+        move |s| Ok(s.utf8()?.contains(re.as_str())?.into_series()),
+        GetOutput::from_type(DataType::Boolean),
+    )
+}
+
+/// 替换匹配的部分，没有 `g` 时只替换第一处
+pub fn replace(expr: Expr, re: Regex, replacement: &str, global: bool) -> Expr {
+    let replacement = replacement_template(replacement);
+    expr.cast(DataType::Utf8).map(
+        move |s| {
+            let ca = s.utf8()?;
+            let out = match global {
+                true => ca.replace_all(re.as_str(), &replacement)?,
+                false => ca.replace(re.as_str(), &replacement)?,
+            };
+            Ok(out.into_series())
+        },
+        GetOutput::from_type(DataType::Utf8),
+    )
+}
+
+/// 取出第一处匹配里的分组，0 是整个匹配，没有匹配时是 null
+pub fn extract(expr: Expr, re: Regex, group: usize) -> Expr {
+    expr.cast(DataType::Utf8).map(
+        move |s| {
+            let mut out: Utf8Chunked = s
+                .utf8()?
+                .into_iter()
+                .map(|v| re.captures(v?)?.get(group).map(|m| m.as_str()))
+                .collect();
+            out.rename(s.name());
+            Ok(out.into_series())
+        },
+        GetOutput::from_type(DataType::Utf8),
+    )
+}
+
+/// SQL 里替换字符串用 `\1` 引用分组、`\&` 引用整个匹配，转换成 regex 的 `${1}`，`$` 本身要转义
+fn replacement_template(replacement: &str) -> String {
+    let mut out = String::with_capacity(replacement.len());
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(d) if d.is_ascii_digit() => out.push_str(&format!("${{{}}}", d)),
+                Some('&') => out.push_str("${0}"),
+                Some(c) => out.push(c),
+                None => out.push('\\'),
+            },
+            '$' => out.push_str("$$"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// 把 SIMILAR TO 的模式转换成正则表达式：`%` 是任意多个字符，`_` 是一个字符，
+/// `|`、`*`、`+`、`?`、`()`、`{}`、`[]` 和正则的含义一样，其它字符按字面匹配，整个字符串都要匹配
+pub fn similar_to(pattern: &str, escape: Option<char>) -> String {
+    let mut out = String::from("(?s)^(?:");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if Some(c) == escape {
+            if let Some(c) = chars.next() {
+                out.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])));
+            }
+            continue;
+        }
+        match c {
+            '%' => out.push_str(".*"),
+            '_' => out.push('.'),
+            '|' | '*' | '+' | '?' | '(' | ')' | '{' | '}' | '[' | ']' => out.push(c),
+            c => out.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    out.push_str(")$");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempFile;
+    use crate::{Context, DataSet, LoadOptions};
+
+    #[test]
+    fn similar_to_works() {
+        let re = Regex::new(&similar_to("a%(b|c)_.txt", Some('\\'))).unwrap();
+        assert!(re.is_match("axxbz.txt"));
+        assert!(re.is_match("acz.txt"));
+        assert!(!re.is_match("acz_txt"));
+        assert!(!re.is_match("xacz.txt"));
+
+        let re = Regex::new(&similar_to("100\\%", Some('\\'))).unwrap();
+        assert!(re.is_match("100%"));
+        assert!(!re.is_match("1000"));
+    }
+
+    #[test]
+    fn replacement_and_flags_work() {
+        assert_eq!(replacement_template(r"\2-\1 \& $5"), "${2}-${1} ${0} $$5");
+        assert_eq!(
+            Flags::parse("gi"),
+            Some(Flags {
+                case_insensitive: true,
+                global: true
+            })
+        );
+        assert_eq!(Flags::parse("x"), None);
+        assert!(matches!(
+            compile("a(b", Flags::default(), Span::empty()),
+            Err(QueryError::InvalidRegex { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn regex_works() {
+        let file = TempFile::new(
+            ".csv",
+            "email,phone\nTom@Example.com,010-1234\nalice@test.org,021-5678\nbob,n/a\n",
+        );
+        let mut ctx = Context::new();
+        ctx.register_source("users", file.uri(), LoadOptions::default());
+        let column = |ds: &DataSet, name: &str| -> Vec<Option<String>> {
+            let s = ds.column(name).unwrap();
+            (0..s.len())
+                .map(|i| match s.get(i) {
+                    AnyValue::Utf8(v) => Some(v.to_string()),
+                    AnyValue::Boolean(v) => Some(v.to_string()),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let ds = ctx
+            .query("SELECT email FROM users WHERE email ~ '@example\\.com$'")
+            .await
+            .unwrap();
+        assert_eq!(ds.height(), 0);
+        let ds = ctx
+            .query("SELECT email FROM users WHERE email ~* '@example\\.com$' OR email !~ '@'")
+            .await
+            .unwrap();
+        assert_eq!(
+            column(&ds, "email"),
+            vec![Some("Tom@Example.com".into()), Some("bob".into())]
+        );
+
+        let sql = "SELECT regexp_matches(phone, '^\\d+-\\d+$') AS valid, \
+                   regexp_replace(phone, '(\\d+)-(\\d+)', '\\2/\\1') AS swapped, \
+                   regexp_replace(email, '[aeiou]', '*', 'gi') AS masked, \
+                   regexp_extract(email, '@([a-z]+)', 1) AS domain \
+                   FROM users";
+        let ds = ctx.query(sql).await.unwrap();
+        assert_eq!(
+            column(&ds, "valid"),
+            vec![
+                Some("true".into()),
+                Some("true".into()),
+                Some("false".into())
+            ]
+        );
+        assert_eq!(column(&ds, "swapped")[1], Some("5678/021".into()));
+        assert_eq!(column(&ds, "masked")[0], Some("T*m@*x*mpl*.c*m".into()));
+        assert_eq!(column(&ds, "domain"), vec![None, Some("test".into()), None]);
+
+        let ds = ctx
+            .query("SELECT phone FROM users WHERE phone SIMILAR TO '0(1|2)%'")
+            .await
+            .unwrap();
+        assert_eq!(ds.height(), 2);
+        let ds = ctx
+            .query("SELECT phone FROM users WHERE phone NOT SIMILAR TO '___-____'")
+            .await
+            .unwrap();
+        assert_eq!(column(&ds, "phone"), vec![Some("n/a".into())]);
+
+        let err = ctx
+            .query("SELECT email FROM users WHERE email ~ 'a(b'")
+            .await;
+        assert!(matches!(err, Err(QueryError::InvalidRegex { .. })));
+        let err = ctx
+            .query("SELECT regexp_extract(email, 'a', 2) FROM users")
+            .await;
+        assert!(matches!(err, Err(QueryError::Unsupported { .. })));
+    }
+}
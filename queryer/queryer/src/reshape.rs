@@ -0,0 +1,337 @@
+use crate::error::{QueryError, Result};
+use crate::validate::validate_columns;
+use polars::prelude::*;
+use sqlparser::ast::Ident;
+
+/// 没有其他列可以分组时，PIVOT 用这个临时列把所有行分到一组
+const PIVOT_KEY: &str = "__pivot_key";
+
+/// PIVOT 支持的聚合函数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PivotAggregate {
+    Sum,
+    Min,
+    Max,
+    Avg,
+    Median,
+    Count,
+    First,
+}
+
+/// PIVOT 的 IN 列表里的一项：pivot 列要等于的值，以及生成的列名
+#[derive(Debug, Clone)]
+pub struct PivotColumn {
+    pub value: Expr,
+    /// polars pivot 生成的列名，也就是值的文本
+    pub key: String,
+    pub alias: String,
+}
+
+/// PIVOT / UNPIVOT，在过滤之前把数据在宽表和长表之间转换
+#[derive(Debug, Clone)]
+pub enum Reshape {
+    /// PIVOT (SUM(value) FOR pivot IN ('a', 'b' AS b))，其余的列作为分组的键
+    Pivot {
+        aggregate: PivotAggregate,
+        value: Ident,
+        pivot: Ident,
+        columns: Vec<PivotColumn>,
+    },
+    /// UNPIVOT [INCLUDE NULLS] (value FOR name IN (a, b AS x))，其余的列保持不变
+    Unpivot {
+        value: String,
+        name: String,
+        columns: Vec<(Ident, String)>,
+        include_nulls: bool,
+    },
+}
+
+impl Reshape {
+    pub fn apply(&self, df: &DataFrame) -> Result<DataFrame> {
+        match self {
+            Reshape::Pivot {
+                aggregate,
+                value,
+                pivot,
+                columns,
+            } => {
+                validate_columns(&[value.clone(), pivot.clone()], df)?;
+                pivot_frame(df, *aggregate, &value.value, &pivot.value, columns)
+            }
+            Reshape::Unpivot {
+                value,
+                name,
+                columns,
+                include_nulls,
+            } => {
+                let idents: Vec<Ident> = columns.iter().map(|(id, _)| id.clone()).collect();
+                validate_columns(&idents, df)?;
+                unpivot_frame(df, value, name, columns, *include_nulls)
+            }
+        }
+    }
+}
+
+fn pivot_frame(
+    df: &DataFrame,
+    aggregate: PivotAggregate,
+    value: &str,
+    pivot: &str,
+    columns: &[PivotColumn],
+) -> Result<DataFrame> {
+    let keys: Vec<&str> = df
+        .get_column_names()
+        .into_iter()
+        .filter(|name| *name != value && *name != pivot)
+        .collect();
+
+    // 和 SQL 的聚合一样忽略 null，只保留 IN 列表里的值
+    let mut lazy = df.clone().lazy().filter(col(value).is_not_null());
+    if let Some(matched) = columns
+        .iter()
+        .map(|c| col(pivot).eq(c.value.clone()))
+        .reduce(|a, b| a.or(b))
+    {
+        lazy = lazy.filter(matched);
+    }
+    if aggregate == PivotAggregate::Avg {
+        lazy = lazy.with_column(col(value).cast(DataType::Float64));
+    }
+    let mut filtered = lazy.collect()?;
+
+    let mut by = keys.clone();
+    if by.is_empty() {
+        filtered.with_column(Series::new(PIVOT_KEY, vec![0i32; filtered.height()]))?;
+        by.push(PIVOT_KEY);
+    }
+    let mut groups = filtered.groupby(by)?;
+    let pivoted = groups.pivot(pivot, value);
+    let out = match aggregate {
+        PivotAggregate::Sum => pivoted.sum(),
+        PivotAggregate::Min => pivoted.min(),
+        PivotAggregate::Max => pivoted.max(),
+        PivotAggregate::Avg => pivoted.mean(),
+        PivotAggregate::Median => pivoted.median(),
+        PivotAggregate::Count => pivoted.count(),
+        PivotAggregate::First => pivoted.first(),
+    }?;
+
+    // 按 IN 列表的顺序输出，数据里没有出现的值输出全是 null 的列
+    let mut result = Vec::with_capacity(keys.len() + columns.len());
+    for key in keys {
+        result.push(out.column(key)?.clone());
+    }
+    for c in columns {
+        let mut s = match out.column(&c.key) {
+            Ok(s) => s.clone(),
+            Err(_) => Float64Chunked::full_null(&c.alias, out.height()).into_series(),
+        };
+        s.rename(&c.alias);
+        result.push(s);
+    }
+    Ok(DataFrame::new(result)?)
+}
+
+fn unpivot_frame(
+    df: &DataFrame,
+    value: &str,
+    name: &str,
+    columns: &[(Ident, String)],
+    include_nulls: bool,
+) -> Result<DataFrame> {
+    let value_vars: Vec<&str> = columns.iter().map(|(id, _)| id.value.as_str()).collect();
+    let id_vars: Vec<&str> = df
+        .get_column_names()
+        .into_iter()
+        .filter(|c| !value_vars.contains(c))
+        .collect();
+    if let Some(c) = id_vars.iter().find(|c| **c == name || **c == value) {
+        return Err(QueryError::execution(format!(
+            "UNPIVOT output column `{}` conflicts with an existing column",
+            c
+        )));
+    }
+
+    // melt 的输出固定叫 variable 和 value，和它们同名的保留列先换成临时的名字
+    let temporary = |c: &str| format!("__unpivot_{}", c);
+    let renamed: Vec<&str> = id_vars
+        .iter()
+        .copied()
+        .filter(|c| matches!(*c, "variable" | "value"))
+        .collect();
+    let mut df = df.clone();
+    for c in &renamed {
+        df.rename(c, &temporary(c))?;
+    }
+    let id_vars: Vec<String> = id_vars
+        .iter()
+        .map(|c| match renamed.contains(c) {
+            true => temporary(c),
+            false => c.to_string(),
+        })
+        .collect();
+
+    let id_vars: Vec<&str> = id_vars.iter().map(String::as_str).collect();
+    let mut out = df.melt(id_vars, value_vars)?;
+
+    // IN 列表里的别名替换掉原来的列名
+    if columns.iter().any(|(id, alias)| id.value != *alias) {
+        let variable: Utf8Chunked = out
+            .column("variable")?
+            .utf8()?
+            .into_iter()
+            .map(|v| {
+                v.map(|v| match columns.iter().find(|(id, _)| id.value == v) {
+                    Some((_, alias)) => alias.as_str(),
+                    None => v,
+                })
+            })
+            .collect();
+        out.replace("variable", variable.into_series())?;
+    }
+    out.rename("variable", name)?;
+    out.rename("value", value)?;
+    for c in &renamed {
+        out.rename(&temporary(c), c)?;
+    }
+
+    if include_nulls {
+        Ok(out)
+    } else {
+        let mask = out.column(value)?.is_not_null();
+        Ok(out.filter(&mask)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempFile;
+    use crate::{Context, LoadOptions, QueryError};
+
+    fn long() -> DataFrame {
+        DataFrame::new(vec![
+            Series::new("date", &["d1", "d1", "d2", "d2", "d2"]),
+            Series::new("location", &["A", "B", "A", "A", "C"]),
+            Series::new("cases", &[Some(1i64), Some(2), Some(3), None, Some(5)]),
+        ])
+        .unwrap()
+    }
+
+    fn pivot_column(value: &str) -> PivotColumn {
+        PivotColumn {
+            value: lit(value),
+            key: value.to_string(),
+            alias: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn pivot_works() {
+        let reshape = Reshape::Pivot {
+            aggregate: PivotAggregate::Sum,
+            value: Ident::new("cases"),
+            pivot: Ident::new("location"),
+            columns: vec![pivot_column("A"), pivot_column("B"), pivot_column("D")],
+        };
+        let df = reshape.apply(&long()).unwrap();
+        let df = df.sort("date", false).unwrap();
+        assert_eq!(df.get_column_names(), ["date", "A", "B", "D"]);
+        assert_eq!(df.column("A").unwrap().get(1), AnyValue::Int64(3));
+        assert_eq!(df.column("B").unwrap().get(1), AnyValue::Null);
+        assert_eq!(df.column("D").unwrap().null_count(), 2);
+
+        // 没有其他列时所有行分到一组
+        let df = long().drop("date").unwrap();
+        let df = reshape.apply(&df).unwrap();
+        assert_eq!(df.get_column_names(), ["A", "B", "D"]);
+        assert_eq!(df.column("A").unwrap().get(0), AnyValue::Int64(4));
+    }
+
+    #[test]
+    fn unpivot_works() {
+        let wide = DataFrame::new(vec![
+            Series::new("date", &["d1", "d2"]),
+            Series::new("a", &[Some(1i64), None]),
+            Series::new("b", &[Some(3i64), Some(4)]),
+        ])
+        .unwrap();
+        let reshape = |include_nulls| Reshape::Unpivot {
+            value: "cases".into(),
+            name: "location".into(),
+            columns: vec![(Ident::new("a"), "A".into()), (Ident::new("b"), "b".into())],
+            include_nulls,
+        };
+
+        let df = reshape(false).apply(&wide).unwrap();
+        assert_eq!(df.get_column_names(), ["date", "location", "cases"]);
+        assert_eq!(df.height(), 3);
+        assert_eq!(df.column("location").unwrap().get(0), AnyValue::Utf8("A"));
+
+        assert_eq!(reshape(true).apply(&wide).unwrap().height(), 4);
+    }
+
+    #[test]
+    fn unpivot_keeps_columns_named_like_melt_output() {
+        let wide = DataFrame::new(vec![
+            Series::new("value", &["v1", "v2"]),
+            Series::new("variable", &["x", "y"]),
+            Series::new("a", &[1i64, 2]),
+            Series::new("b", &[3i64, 4]),
+        ])
+        .unwrap();
+        let reshape = |value: &str, name: &str| Reshape::Unpivot {
+            value: value.into(),
+            name: name.into(),
+            columns: vec![(Ident::new("a"), "a".into()), (Ident::new("b"), "b".into())],
+            include_nulls: false,
+        };
+
+        let df = reshape("cases", "location").apply(&wide).unwrap();
+        assert_eq!(
+            df.get_column_names(),
+            ["value", "variable", "location", "cases"]
+        );
+        assert_eq!(df.column("value").unwrap().get(1), AnyValue::Utf8("v2"));
+        assert_eq!(df.column("variable").unwrap().get(1), AnyValue::Utf8("y"));
+        assert_eq!(df.column("location").unwrap().get(2), AnyValue::Utf8("b"));
+        assert_eq!(df.column("cases").unwrap().get(2), AnyValue::Int64(3));
+
+        assert!(reshape("value", "location").apply(&wide).is_err());
+    }
+
+    #[tokio::test]
+    async fn pivot_and_unpivot_work() {
+        let file = TempFile::new(
+            ".csv",
+            "date,location,new_cases\n\
+             2021-01-01,France,1\n2021-01-01,Italy,2\n\
+             2021-01-02,France,3\n2021-01-02,France,4\n2021-01-02,Spain,5\n",
+        );
+        let mut ctx = Context::new();
+        ctx.register_source("covid", file.uri(), LoadOptions::default());
+
+        let sql = "SELECT date, fr, Italy FROM covid \
+                   PIVOT (SUM(new_cases) FOR location IN ('France' AS fr, 'Italy')) \
+                   WHERE fr > 1";
+        let ds = ctx.query(sql).await.unwrap();
+        assert_eq!(ds.height(), 1);
+        assert_eq!(ds.column("fr").unwrap().get(0), AnyValue::Int64(7));
+        assert_eq!(ds.column("Italy").unwrap().get(0), AnyValue::Null);
+
+        // 先转成宽表再转回长表
+        let sql = "SELECT date, location, cases FROM covid \
+                   PIVOT (SUM(new_cases) FOR location IN ('France', 'Italy')) \
+                   UNPIVOT (cases FOR location IN (France, Italy)) \
+                   ORDER BY date, location";
+        let ds = ctx.query(sql).await.unwrap();
+        assert_eq!(ds.height(), 3);
+        assert_eq!(ds.column("cases").unwrap().get(2), AnyValue::Int64(7));
+
+        let err = ctx
+            .query("SELECT * FROM covid PIVOT (SUM(new_case) FOR location IN ('France'))")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, QueryError::UnknownColumn { .. }));
+    }
+}
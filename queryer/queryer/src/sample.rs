@@ -0,0 +1,144 @@
+use crate::error::Result;
+use polars::prelude::*;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// 抽样的大小
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleSize {
+    /// `TABLESAMPLE BERNOULLI (10)`，每一行以 10% 的概率被选中
+    Percent(f64),
+    /// `SAMPLE 10 ROWS`，随机选出 10 行
+    Rows(usize),
+}
+
+/// TABLESAMPLE / SAMPLE 子句，在过滤之前作用在加载出来的数据上。
+/// 给了 seed 时结果是确定的，同样的数据和 seed 每次选出同样的行
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub size: SampleSize,
+    pub seed: Option<u64>,
+}
+
+impl Sample {
+    /// 对 df 抽样，选中的行保持原来的顺序
+    pub fn apply(&self, df: &DataFrame) -> Result<DataFrame> {
+        let height = df.height();
+        let mut rng = SplitMix64::new(self.seed.unwrap_or_else(random_seed));
+        let mask: BooleanChunked = match self.size {
+            SampleSize::Percent(p) => (0..height).map(|_| rng.next_f64() * 100.0 < p).collect(),
+            SampleSize::Rows(n) if n >= height => return Ok(df.clone()),
+            SampleSize::Rows(n) => {
+                // 部分 Fisher-Yates 洗牌，只需要打乱前 n 个位置
+                let mut indices: Vec<usize> = (0..height).collect();
+                for i in 0..n {
+                    let j = i + (rng.next_u64() % (height - i) as u64) as usize;
+                    indices.swap(i, j);
+                }
+                let mut selected = vec![false; height];
+                for &i in &indices[..n] {
+                    selected[i] = true;
+                }
+                selected.into_iter().collect()
+            }
+        };
+        Ok(df.filter(&mask)?)
+    }
+}
+
+fn random_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// 简单的伪随机数生成器，足够用来抽样，也不需要引入额外的依赖
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// [0, 1) 之间的浮点数
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::numbers_context;
+
+    fn df() -> DataFrame {
+        DataFrame::new(vec![Series::new("a", (0..100).collect::<Vec<i64>>())]).unwrap()
+    }
+
+    #[test]
+    fn sample_rows_works() {
+        let sample = Sample {
+            size: SampleSize::Rows(10),
+            seed: Some(42),
+        };
+        let a = sample.apply(&df()).unwrap();
+        let b = sample.apply(&df()).unwrap();
+        assert_eq!(a.height(), 10);
+        assert!(a.frame_equal(&b));
+
+        // 选中的行保持原来的顺序
+        let values: Vec<i64> = a
+            .column("a")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert!(values.windows(2).all(|w| w[0] < w[1]));
+
+        let all = Sample {
+            size: SampleSize::Rows(1000),
+            seed: None,
+        };
+        assert_eq!(all.apply(&df()).unwrap().height(), 100);
+    }
+
+    #[test]
+    fn sample_percent_works() {
+        let sample = |p, seed| Sample {
+            size: SampleSize::Percent(p),
+            seed: Some(seed),
+        };
+        assert_eq!(sample(0.0, 1).apply(&df()).unwrap().height(), 0);
+        assert_eq!(sample(100.0, 1).apply(&df()).unwrap().height(), 100);
+
+        let a = sample(30.0, 7).apply(&df()).unwrap();
+        assert!(a.frame_equal(&sample(30.0, 7).apply(&df()).unwrap()));
+        assert!(a.height() > 10 && a.height() < 50);
+    }
+
+    #[tokio::test]
+    async fn table_sample_works() {
+        let (ctx, _file) = numbers_context();
+
+        let ds = ctx
+            .query("SELECT name FROM numbers SAMPLE 5 ROWS")
+            .await
+            .unwrap();
+        assert_eq!(ds.height(), 5);
+
+        // 先抽样再过滤，同样的 seed 结果一样
+        let sql = "SELECT name, value FROM numbers TABLESAMPLE BERNOULLI (50) REPEATABLE (3) \
+                   WHERE value >= 10";
+        let a = ctx.query(sql).await.unwrap();
+        let b = ctx.query(sql).await.unwrap();
+        assert!(a.frame_equal(&b));
+        assert!(a.height() <= 11);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::error::{QueryError, Result};
+use crate::limits::check_rows;
+use crate::loader::{apply_schema, parse_dates, Load, LoadOptions};
+use crate::DataSet;
+use percent_encoding::percent_decode_str;
+use polars::prelude::*;
+use rusqlite::types::Value;
+use rusqlite::{Connection, OpenFlags};
+use sqlparser::ast::Statement;
+use sqlparser::dialect::SQLiteDialect;
+use sqlparser::parser::Parser;
+
+pub const SQLITE_PREFIX: &str = "sqlite://";
+
+/// SQLite 数据源。`sqlite:///path/db.sqlite?table=countries` 读取整张表，
+/// `sqlite:///path/db.sqlite?query=SELECT ...` 把查询交给 SQLite 执行，只接受一条 SELECT。
+/// 表名和查询按 URL 的规则解码，`%20` 是空格，`&`、`#` 要写成 `%26`、`%23`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqliteLoader {
+    uri: String,
+    path: String,
+    query: String,
+}
+
+impl SqliteLoader {
+    pub fn new(uri: &str) -> Result<Self> {
+        let (path, param) = uri
+            .strip_prefix(SQLITE_PREFIX)
+            .and_then(|rest| rest.split_once('?'))
+            .ok_or_else(|| {
+                QueryError::fetch(uri, "expect sqlite:///path?table=NAME or ?query=SQL")
+            })?;
+        let decode = |v: &str| match percent_decode_str(v).decode_utf8() {
+            Ok(v) => Ok(v.into_owned()),
+            Err(e) => Err(QueryError::fetch(uri, e)),
+        };
+        let query = match param.split_once('=') {
+            Some(("table", table)) if !table.is_empty() => {
+                let table = decode(table)?;
+                format!("SELECT * FROM \"{}\"", table.replace('"', "\"\""))
+            }
+            Some(("query", query)) if !query.is_empty() => {
+                let query = decode(query)?;
+                check_select(&query)?;
+                query
+            }
+            _ => return Err(QueryError::fetch(uri, "expect ?table=NAME or ?query=SQL")),
+        };
+
+        Ok(Self {
+            uri: uri.to_string(),
+            path: path.to_string(),
+            query,
+        })
+    }
+
+    /// 数据库文件的路径
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// `?query=` 只能是一条 SELECT，ATTACH、PRAGMA 之类的语句不能通过数据源执行
+fn check_select(query: &str) -> Result<()> {
+    let statements = Parser::parse_sql(&SQLiteDialect {}, query)
+        .map_err(|e| QueryError::NotAllowed(format!("?query= is not a valid SELECT: {}", e)))?;
+    match statements.as_slice() {
+        [Statement::Query(_)] => Ok(()),
+        _ => Err(QueryError::NotAllowed(
+            "?query= only accepts a single SELECT statement".to_string(),
+        )),
+    }
+}
+
+impl Load for SqliteLoader {
+    type Error = QueryError;
+
+    fn load(self, options: &LoadOptions) -> Result<DataSet, Self::Error> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let conn = Connection::open_with_flags(&self.path, flags)
+            .map_err(|e| QueryError::fetch(&self.uri, e))?;
+        let error = |e: rusqlite::Error| QueryError::Load(format!("{}: {}", self.uri, e));
+
+        let mut stmt = conn.prepare(&self.query).map_err(error)?;
+        if !stmt.readonly() {
+            return Err(QueryError::NotAllowed(format!(
+                "{}: only read-only queries are allowed",
+                self.uri
+            )));
+        }
+        let names: Vec<String> = stmt.column_names().into_iter().map(Into::into).collect();
+        let declared: Vec<Option<String>> = stmt
+            .columns()
+            .iter()
+            .map(|c| c.decl_type().map(str::to_uppercase))
+            .collect();
+
+        let mut values = vec![Vec::new(); names.len()];
+        let mut rows = stmt.query([]).map_err(error)?;
+        let mut count = 0;
+        while let Some(row) = rows.next().map_err(error)? {
+            count += 1;
+            check_rows(&self.uri, count, options.max_rows)?;
+            for (i, column) in values.iter_mut().enumerate() {
+                column.push(row.get::<_, Value>(i).map_err(error)?);
+            }
+        }
+
+        let columns = names
+            .iter()
+            .zip(declared)
+            .zip(values)
+            .map(|((name, declared), values)| to_series(name, declared.as_deref(), values))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut df = apply_schema(DataFrame::new(columns)?, &options.schema)?;
+        if options.parse_dates {
+            df = parse_dates(df, &options.schema)?;
+        }
+        Ok(DataSet(df))
+    }
+}
+
+/// SQLite 的列没有固定的类型，按实际的值决定：全是整数时是 Int64，有小数时是 Float64，
+/// 有文本时是 Utf8。整列都是 NULL 时按声明的类型
+fn to_series(name: &str, declared: Option<&str>, values: Vec<Value>) -> Result<Series> {
+    const INTEGER: u8 = 1;
+    const REAL: u8 = 2;
+    const TEXT: u8 = 3;
+
+    let mut kind = 0;
+    for v in &values {
+        kind = kind.max(match v {
+            Value::Null => 0,
+            Value::Integer(_) => INTEGER,
+            Value::Real(_) => REAL,
+            Value::Text(_) => TEXT,
+            Value::Blob(_) => {
+                return Err(QueryError::Load(format!(
+                    "column `{}` contains BLOB values, which are not supported",
+                    name
+                )))
+            }
+        });
+    }
+    if kind == 0 {
+        // SQLite 的类型亲和性规则
+        kind = match declared.unwrap_or_default() {
+            t if t.contains("INT") => INTEGER,
+            t if t.contains("REAL") || t.contains("FLOA") || t.contains("DOUB") => REAL,
+            _ => TEXT,
+        };
+    }
+
+    Ok(match kind {
+        INTEGER => {
+            let values: Vec<Option<i64>> = values
+                .iter()
+                .map(|v| match v {
+                    Value::Integer(v) => Some(*v),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+        REAL => {
+            let values: Vec<Option<f64>> = values
+                .iter()
+                .map(|v| match v {
+                    Value::Integer(v) => Some(*v as f64),
+                    Value::Real(v) => Some(*v),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+        _ => {
+            let values: Vec<Option<String>> = values
+                .into_iter()
+                .map(|v| match v {
+                    Value::Integer(v) => Some(v.to_string()),
+                    Value::Real(v) => Some(v.to_string()),
+                    Value::Text(v) => Some(v),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempFile;
+    use crate::Context;
+
+    fn countries_db() -> TempFile {
+        let file = TempFile::new(".db", "");
+        let conn = Connection::open(file.path()).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE countries (code TEXT, name TEXT, population INTEGER, area REAL, founded TEXT);
+             INSERT INTO countries VALUES ('CN', 'China', 1412, 9597000, '1949-10-01');
+             INSERT INTO countries VALUES ('FR', 'France', 68, 551695.5, NULL);
+             INSERT INTO countries VALUES ('XX', NULL, NULL, NULL, NULL);",
+        )
+        .unwrap();
+        file
+    }
+
+    #[test]
+    fn sqlite_loader_works() {
+        let file = countries_db();
+        let path = file.path().display();
+        let uri = format!("sqlite://{}?table=countries", path);
+        let ds = SqliteLoader::new(&uri)
+            .unwrap()
+            .load(&LoadOptions::default().with_parse_dates(true))
+            .unwrap();
+        assert_eq!(ds.shape(), (3, 5));
+        assert_eq!(ds.column("population").unwrap().dtype(), &DataType::Int64);
+        assert_eq!(ds.column("area").unwrap().dtype(), &DataType::Float64);
+        assert_eq!(ds.column("founded").unwrap().dtype(), &DataType::Date32);
+
+        let uri = format!(
+            "sqlite://{}?query=SELECT code FROM countries WHERE population > 100",
+            path
+        );
+        let ds = SqliteLoader::new(&uri)
+            .unwrap()
+            .load(&LoadOptions::default())
+            .unwrap();
+        assert_eq!(ds.column("code").unwrap().get(0), AnyValue::Utf8("CN"));
+        assert_eq!(ds.height(), 1);
+
+        // 编码过的查询，里面有 `&`、`#` 和 `%`
+        let uri = format!(
+            "sqlite://{}?query=SELECT%20code%20FROM%20countries%20WHERE%20name%20%3D%20'A%26B%23%25'%20OR%20code%20%3D%20'FR'",
+            path
+        );
+        let ds = SqliteLoader::new(&uri)
+            .unwrap()
+            .load(&LoadOptions::default())
+            .unwrap();
+        assert_eq!(ds.column("code").unwrap().get(0), AnyValue::Utf8("FR"));
+        assert_eq!(ds.height(), 1);
+
+        assert!(SqliteLoader::new("sqlite:///tmp/x.db").is_err());
+        for query in [
+            "ATTACH DATABASE '/tmp/other.db' AS other",
+            "PRAGMA table_info(countries)",
+            "DELETE FROM countries",
+            "SELECT 1; DROP TABLE countries",
+            "SELEC code FROM countries",
+        ] {
+            let uri = format!("sqlite://{}?query={}", path, query);
+            assert!(
+                matches!(SqliteLoader::new(&uri), Err(QueryError::NotAllowed(_))),
+                "{}",
+                query
+            );
+        }
+        let missing = SqliteLoader::new("sqlite:///no/such.db?table=t").unwrap();
+        assert!(matches!(
+            missing.load(&LoadOptions::default()),
+            Err(QueryError::Fetch { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn sqlite_source_works() {
+        let file = countries_db();
+        let mut ctx = Context::new();
+        let uri = format!("sqlite://{}?table=countries", file.path().display());
+        ctx.register_source("countries", uri, LoadOptions::default());
+        let ds = ctx
+            .query("SELECT code FROM countries WHERE population > 50 ORDER BY population")
+            .await
+            .unwrap();
+        assert_eq!(ds.column("code").unwrap().get(0), AnyValue::Utf8("FR"));
+        assert_eq!(ds.column("code").unwrap().get(1), AnyValue::Utf8("CN"));
+    }
+}
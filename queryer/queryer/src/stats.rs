@@ -0,0 +1,92 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::{debug, Instrument, Span};
+
+/// 一次查询各个阶段的耗时和数据量。每个阶段同时有一个同名的 tracing span，
+/// span 上的 `elapsed_ms`、`bytes`、`rows` 字段和这里的值一致
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryStats {
+    /// 解析 SQL，绑定参数
+    pub parse: Duration,
+    /// 把 AST 转换成 polars 的表达式
+    pub plan: Duration,
+    /// 下载或者读取数据源
+    pub fetch: Duration,
+    /// 把数据源解析成 DataFrame
+    pub load: Duration,
+    /// polars 的计算
+    pub execute: Duration,
+    /// COPY 写文件时序列化用的时间，普通查询的结果由调用方序列化
+    pub serialize: Duration,
+    /// 从数据源读到的字节数，SQLite 数据源不计算
+    pub bytes_fetched: u64,
+    /// 从数据源加载的行数
+    pub rows_loaded: usize,
+    /// 结果的行数
+    pub rows_returned: usize,
+    /// 结果来自缓存，这时 fetch、load、execute 都是零
+    pub cached: bool,
+}
+
+impl QueryStats {
+    /// 各个阶段加起来的时间
+    pub fn total(&self) -> Duration {
+        self.parse + self.plan + self.fetch + self.load + self.execute + self.serialize
+    }
+}
+
+/// 在 span 里执行一个阶段，把耗时记到 span 的 `elapsed_ms` 字段上
+pub(crate) fn timed<T>(span: &Span, f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = span.in_scope(f);
+    (result, finish(span, start))
+}
+
+/// 异步版本的 [`timed`]
+pub(crate) async fn timed_async<F: Future>(span: &Span, f: F) -> (F::Output, Duration) {
+    let start = Instant::now();
+    let result = f.instrument(span.clone()).await;
+    (result, finish(span, start))
+}
+
+fn finish(span: &Span, start: Instant) -> Duration {
+    let elapsed = start.elapsed();
+    span.record("elapsed_ms", &(elapsed.as_secs_f64() * 1000.0));
+    span.in_scope(|| debug!("finished"));
+    elapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::TempFile;
+    use crate::{CacheOptions, Context, LoadOptions, Params, QueryOptions};
+
+    #[tokio::test]
+    async fn query_stats_are_collected() {
+        let file = TempFile::new(".csv", "name,value\na,1\nb,2\nc,3\n");
+        let mut ctx = Context::new();
+        ctx.register_source("numbers", file.uri(), LoadOptions::default());
+        ctx.enable_cache(CacheOptions::default());
+
+        let sql = "SELECT * FROM numbers LIMIT 2";
+        let (ds, stats) = ctx
+            .query_with_stats(sql, Params::new(), QueryOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(stats.rows_returned, ds.height());
+        assert_eq!(stats.rows_returned, 2);
+        assert_eq!(stats.rows_loaded, 3);
+        assert_eq!(stats.bytes_fetched, 23);
+        assert!(!stats.cached);
+        assert!(stats.total() >= stats.fetch + stats.execute);
+
+        // 命中缓存时不会再获取数据
+        let (_, stats) = ctx
+            .query_with_stats(sql, Params::new(), QueryOptions::default())
+            .await
+            .unwrap();
+        assert!(stats.cached);
+        assert_eq!((stats.bytes_fetched, stats.rows_loaded), (0, 0));
+        assert_eq!(stats.rows_returned, 2);
+    }
+}
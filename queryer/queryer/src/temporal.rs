@@ -0,0 +1,390 @@
+use crate::error::{QueryError, Result};
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, Timelike};
+use polars::prelude::*;
+use std::convert::TryFrom;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+/// `%.f` 可以匹配空的小数部分，所以也能解析没有毫秒的时间
+const DATETIME_FORMATS: [&str; 2] = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"];
+
+/// 解析 `2021-01-31` 格式的日期
+pub fn parse_date(s: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(s.trim(), DATE_FORMAT).ok()
+}
+
+/// 解析 `2021-01-31 12:00:00` / `2021-01-31T12:00:00` 格式的时间，只有日期时按零点处理
+pub fn parse_datetime(s: &str) -> Option<NaiveDateTime> {
+    let s = s.trim();
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+        .or_else(|| parse_date(s)?.and_hms_opt(0, 0, 0))
+}
+
+/// 把 Utf8 列按日期（Date32）或时间（Date64）解析，解析不了的值变成 null
+pub fn parse_temporal(s: &Series, dtype: &DataType) -> Result<Series> {
+    let ca = s.utf8()?;
+    let mut parsed = match dtype {
+        DataType::Date32 => ca
+            .into_iter()
+            .map(|v| v.and_then(parse_date).map(date_to_days))
+            .collect::<Date32Chunked>()
+            .into_series(),
+        DataType::Date64 => ca
+            .into_iter()
+            .map(|v| v.and_then(parse_datetime).map(|dt| dt.timestamp_millis()))
+            .collect::<Date64Chunked>()
+            .into_series(),
+        dtype => {
+            return Err(QueryError::Load(format!(
+                "cannot parse column `{}` as {:?}",
+                s.name(),
+                dtype
+            )))
+        }
+    };
+    parsed.rename(s.name());
+    Ok(parsed)
+}
+
+/// 如果 Utf8 列的所有值都是日期或时间，返回解析后的列
+pub fn detect_temporal(s: &Series) -> Option<Series> {
+    if s.dtype() != &DataType::Utf8 {
+        return None;
+    }
+    let first = s.utf8().ok()?.into_iter().flatten().next()?;
+    let dtype = if parse_date(first).is_some() {
+        DataType::Date32
+    } else if parse_datetime(first).is_some() {
+        DataType::Date64
+    } else {
+        return None;
+    };
+
+    let parsed = parse_temporal(s, &dtype).ok()?;
+    (parsed.null_count() == s.null_count()).then_some(parsed)
+}
+
+/// 1970-01-01 是公元第几天
+const UNIX_EPOCH_DAYS_FROM_CE: i32 = 719_163;
+
+fn date_to_days(d: NaiveDate) -> i32 {
+    d.num_days_from_ce() - UNIX_EPOCH_DAYS_FROM_CE
+}
+
+/// 对 Date32 / Date64 列逐个值做变换，f 的输入输出都是 NaiveDateTime。
+/// keep_date 为 true 时 Date32 的输入仍然输出 Date32，否则统一输出 Date64
+fn map_datetime<F>(s: Series, keep_date: bool, f: F) -> std::result::Result<Series, PolarsError>
+where
+    F: Fn(NaiveDateTime) -> Option<NaiveDateTime>,
+{
+    let dtype = s.dtype().clone();
+    if !matches!(dtype, DataType::Date32 | DataType::Date64) {
+        return Err(PolarsError::ComputeError(
+            format!("expect a date or timestamp, got {:?}", dtype).into(),
+        ));
+    }
+
+    let millis = s.cast_with_dtype(&DataType::Date64)?;
+    let mut out = millis
+        .date64()?
+        .into_iter()
+        .map(|v| {
+            let ms = match v {
+                Some(ms) => ms,
+                None => return Ok(None),
+            };
+            let nanos = ms.rem_euclid(1000) as u32 * 1_000_000;
+            let dt =
+                NaiveDateTime::from_timestamp_opt(ms.div_euclid(1000), nanos).ok_or_else(|| {
+                    PolarsError::ComputeError(format!("timestamp {} is out of range", ms).into())
+                })?;
+            Ok(f(dt).map(|dt| dt.timestamp_millis()))
+        })
+        .collect::<std::result::Result<Date64Chunked, PolarsError>>()?
+        .into_series();
+    out.rename(s.name());
+
+    match dtype {
+        DataType::Date32 if keep_date => out.cast_with_dtype(&DataType::Date32),
+        _ => Ok(out),
+    }
+}
+
+/// INTERVAL，月份和固定长度的部分分开保存，因为每个月的天数不一样
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Interval {
+    pub months: i64,
+    pub millis: i64,
+}
+
+impl Interval {
+    /// 解析 `'7 days'`、`'1 year 2 months'` 这样的写法。
+    /// unit 是 `INTERVAL '7' DAY` 里的单位，这时 value 只能是数字
+    pub fn parse(value: &str, unit: Option<&str>) -> Option<Self> {
+        let mut interval = Interval::default();
+        let mut tokens = value.split_whitespace().peekable();
+        tokens.peek()?;
+        while let Some(n) = tokens.next() {
+            let n: i64 = n.parse().ok()?;
+            let unit = match unit {
+                Some(unit) => unit.to_lowercase(),
+                None => tokens.next()?.to_lowercase(),
+            };
+            interval = interval.add(n, &unit)?;
+        }
+        // 减去 INTERVAL 时要取反，取反也不能溢出
+        interval.months.checked_neg()?;
+        interval.millis.checked_neg()?;
+        Some(interval)
+    }
+
+    /// 加上 n 个 unit，数字太大溢出时返回 `None`
+    fn add(mut self, n: i64, unit: &str) -> Option<Self> {
+        const SECOND: i64 = 1000;
+        let (months, millis) = match unit {
+            "year" | "years" => (12, 0),
+            "month" | "months" | "mon" | "mons" => (1, 0),
+            "week" | "weeks" => (0, 7 * 86_400 * SECOND),
+            "day" | "days" => (0, 86_400 * SECOND),
+            "hour" | "hours" => (0, 3600 * SECOND),
+            "minute" | "minutes" | "min" | "mins" => (0, 60 * SECOND),
+            "second" | "seconds" | "sec" | "secs" => (0, SECOND),
+            "millisecond" | "milliseconds" | "ms" => (0, 1),
+            _ => return None,
+        };
+        self.months = self.months.checked_add(n.checked_mul(months)?)?;
+        self.millis = self.millis.checked_add(n.checked_mul(millis)?)?;
+        Some(self)
+    }
+
+    /// 是否只包含整天，这样 DATE 加减之后还是 DATE
+    fn whole_days(&self) -> bool {
+        self.millis % (86_400 * 1000) == 0
+    }
+
+    fn negate(self) -> Self {
+        Self {
+            months: -self.months,
+            millis: -self.millis,
+        }
+    }
+
+    fn add_to(&self, dt: NaiveDateTime) -> Option<NaiveDateTime> {
+        let dt = add_months(dt, self.months)?;
+        dt.checked_add_signed(Duration::milliseconds(self.millis))
+    }
+}
+
+/// 加上若干个月，日期超过当月天数时取当月最后一天
+fn add_months(dt: NaiveDateTime, months: i64) -> Option<NaiveDateTime> {
+    if months == 0 {
+        return Some(dt);
+    }
+    let total = (dt.year() as i64 * 12 + dt.month0() as i64).checked_add(months)?;
+    let year = i32::try_from(total.div_euclid(12)).ok()?;
+    let month = total.rem_euclid(12) as u32 + 1;
+    let day = (1..=dt.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))?;
+    Some(day.and_time(dt.time()))
+}
+
+/// expr 加上（negative 为 true 时减去）一个 INTERVAL
+pub fn shift(expr: Expr, interval: Interval, negative: bool) -> Expr {
+    let interval = if negative {
+        interval.negate()
+    } else {
+        interval
+    };
+    let keep_date = interval.months != 0 || interval.whole_days();
+    let output = if keep_date {
+        GetOutput::same_type()
+    } else {
+        GetOutput::from_type(DataType::Date64)
+    };
+    expr.map(
+        move |s| map_datetime(s, keep_date, |dt| interval.add_to(dt)),
+        output,
+    )
+}
+
+/// DATE_TRUNC('month', expr)，把时间截断到给定的精度
+pub fn date_trunc(unit: &str, expr: Expr) -> Option<Expr> {
+    let unit = unit.to_lowercase();
+    let keep_date = matches!(unit.as_str(), "year" | "quarter" | "month" | "week" | "day");
+    let trunc = move |dt: NaiveDateTime| {
+        let date = dt.date();
+        let time = dt.time();
+        match unit.as_str() {
+            "year" => NaiveDate::from_ymd_opt(date.year(), 1, 1)?.and_hms_opt(0, 0, 0),
+            "quarter" => NaiveDate::from_ymd_opt(date.year(), date.month0() / 3 * 3 + 1, 1)?
+                .and_hms_opt(0, 0, 0),
+            "month" => NaiveDate::from_ymd_opt(date.year(), date.month(), 1)?.and_hms_opt(0, 0, 0),
+            "week" => {
+                let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+                monday.and_hms_opt(0, 0, 0)
+            }
+            "day" => date.and_hms_opt(0, 0, 0),
+            "hour" => date.and_hms_opt(time.hour(), 0, 0),
+            "minute" => date.and_hms_opt(time.hour(), time.minute(), 0),
+            "second" => date.and_hms_opt(time.hour(), time.minute(), time.second()),
+            _ => None,
+        }
+    };
+
+    // 先检查一遍单位，避免执行时才报错
+    trunc(NaiveDateTime::from_timestamp_opt(0, 0)?)?;
+
+    let output = if keep_date {
+        GetOutput::same_type()
+    } else {
+        GetOutput::from_type(DataType::Date64)
+    };
+    Some(expr.map(move |s| map_datetime(s, keep_date, &trunc), output))
+}
+
+/// EXTRACT 支持的字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateField {
+    Year,
+    Quarter,
+    Month,
+    Week,
+    Day,
+    /// 星期几，周日是 0
+    DayOfWeek,
+    /// ISO 的星期几，周一是 1，周日是 7
+    IsoDayOfWeek,
+    DayOfYear,
+    Hour,
+    Minute,
+    Second,
+}
+
+/// EXTRACT(field FROM expr)，返回 UInt32
+pub fn extract(field: DateField, expr: Expr) -> Expr {
+    match field {
+        DateField::Year => expr.year(),
+        DateField::Quarter => (expr.month() - lit(1u32)) / lit(3u32) + lit(1u32),
+        DateField::Month => expr.month(),
+        DateField::Week => expr.week(),
+        DateField::Day => expr.day(),
+        DateField::DayOfWeek => (expr.weekday() + lit(1u32)) % lit(7u32),
+        DateField::IsoDayOfWeek => expr.weekday() + lit(1u32),
+        DateField::DayOfYear => expr.ordinal_day(),
+        DateField::Hour => expr.hour(),
+        DateField::Minute => expr.minute(),
+        DateField::Second => expr.second(),
+    }
+}
+
+/// NOW()，查询开始时的本地时间
+pub fn now() -> Expr {
+    lit(Local::now().naive_local())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempFile;
+    use crate::{Context, LoadOptions};
+
+    fn datetime(year: i32, month: u32, day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .and_then(|d| d.and_hms_opt(hour, 0, 0))
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_interval_works() {
+        let day = 86_400_000;
+        assert_eq!(
+            Interval::parse("7 days", None),
+            Some(Interval {
+                months: 0,
+                millis: 7 * day
+            })
+        );
+        assert_eq!(
+            Interval::parse("1 year 2 Months 3 hours", None),
+            Some(Interval {
+                months: 14,
+                millis: 3 * 3_600_000
+            })
+        );
+        assert_eq!(
+            Interval::parse("2", Some("week")),
+            Some(Interval {
+                months: 0,
+                millis: 14 * day
+            })
+        );
+        assert_eq!(Interval::parse("0 days", None), Some(Interval::default()));
+        assert_eq!(Interval::parse("7 fortnights", None), None);
+        assert_eq!(Interval::parse("days", None), None);
+        assert_eq!(Interval::parse(" ", None), None);
+    }
+
+    #[test]
+    fn interval_overflow_is_rejected() {
+        assert_eq!(Interval::parse("9999999999999 weeks", None), None);
+        assert_eq!(Interval::parse("800000000000000000 years", None), None);
+        assert_eq!(Interval::parse("9223372036854775807 ms 1 ms", None), None);
+        assert_eq!(Interval::parse("-9223372036854775808", Some("ms")), None);
+
+        let dt = datetime(2021, 1, 31, 8);
+        assert_eq!(add_months(dt, i64::MAX), None);
+    }
+
+    #[test]
+    fn out_of_range_timestamp_is_error() {
+        let s = Series::new("t", &[i64::MAX])
+            .cast_with_dtype(&DataType::Date64)
+            .unwrap();
+        assert!(map_datetime(s, false, Some).is_err());
+    }
+
+    #[test]
+    fn add_months_clamps_day() {
+        let dt = datetime(2021, 1, 31, 8);
+        assert_eq!(add_months(dt, 1), Some(datetime(2021, 2, 28, 8)));
+        assert_eq!(add_months(dt, -2), Some(datetime(2020, 11, 30, 8)));
+    }
+
+    #[test]
+    fn detect_temporal_works() {
+        let s = Series::new("d", &[Some("2021-01-01"), None, Some("2021-03-15")]);
+        assert_eq!(detect_temporal(&s).unwrap().dtype(), &DataType::Date32);
+
+        let s = Series::new("t", &["2021-01-01 10:00:00", "2021-01-01T11:30:00.5"]);
+        assert_eq!(detect_temporal(&s).unwrap().dtype(), &DataType::Date64);
+
+        let s = Series::new("x", &["2021-01-01", "not a date"]);
+        assert!(detect_temporal(&s).is_none());
+    }
+
+    #[tokio::test]
+    async fn temporal_query_works() {
+        let data = "day,cases\n2021-01-30,1\n2021-01-31,2\n2021-02-01,3\n2021-02-28,4\n";
+        let file = TempFile::new(".csv", data);
+        let mut ctx = Context::new();
+        let options = LoadOptions::default().with_parse_dates(true);
+        ctx.register_source("t", file.uri(), options);
+
+        let sql = "SELECT day, EXTRACT(MONTH FROM day) AS m, DATE_TRUNC('month', day) AS month, \
+                   day + INTERVAL '1 month' AS next, day - INTERVAL '0 days' AS same FROM t \
+                   WHERE day BETWEEN DATE '2021-01-31' AND DATE '2021-02-28' - INTERVAL '1 day' \
+                   AND day < NOW()";
+        let ds = ctx.query(sql).await.unwrap();
+        assert_eq!(ds.height(), 2);
+        assert_eq!(ds.column("day").unwrap().dtype(), &DataType::Date32);
+        let row = |name: &str, i| ds.column(name).unwrap().get(i).to_string();
+        assert_eq!(row("day", 0), "2021-01-31");
+        assert_eq!(row("m", 1), "2");
+        assert_eq!(row("month", 0), "2021-01-01");
+        assert_eq!(row("next", 0), "2021-02-28");
+        assert_eq!(row("next", 1), "2021-03-01");
+        assert_eq!(row("same", 1), "2021-02-01");
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{Context, LoadOptions};
+use std::io::Write;
+use std::path::Path;
+use tempfile::{Builder, NamedTempFile};
+
+/// 测试用的临时文件，文件名唯一，drop 的时候删除，并行或者重复执行的测试互不影响
+pub(crate) struct TempFile(NamedTempFile);
+
+impl TempFile {
+    /// 创建一个以 suffix 结尾的临时文件，写入 content
+    pub fn new(suffix: &str, content: impl AsRef<[u8]>) -> Self {
+        let mut file = Builder::new()
+            .prefix("queryer_")
+            .suffix(suffix)
+            .tempfile()
+            .unwrap();
+        file.write_all(content.as_ref()).unwrap();
+        file.flush().unwrap();
+        Self(file)
+    }
+
+    pub fn path(&self) -> &Path {
+        self.0.path()
+    }
+
+    /// 文件的 `file://` 地址
+    pub fn uri(&self) -> String {
+        format!("file://{}", self.path().display())
+    }
+}
+
+/// name,value 的 csv 内容，前 20 行 value 是整数，最后一行是小数
+pub(crate) fn numbers() -> String {
+    let mut content = String::from("name,value\n");
+    for i in 0..20 {
+        content.push_str(&format!("row{},{}\n", i, i));
+    }
+    content.push_str("last,1.5\n");
+    content
+}
+
+/// 内容是 [`numbers`] 的 csv 文件
+pub(crate) fn numbers_csv() -> TempFile {
+    TempFile::new(".csv", numbers())
+}
+
+/// 注册了 `numbers` 数据源（读整个文件推断类型）的 Context，返回的文件要和它活得一样久
+pub(crate) fn numbers_context() -> (Context, TempFile) {
+    let file = numbers_csv();
+    let mut ctx = Context::new();
+    let options = LoadOptions::default().infer_full_schema();
+    ctx.register_source("numbers", file.uri(), options);
+    (ctx, file)
+}
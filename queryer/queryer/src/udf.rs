@@ -0,0 +1,146 @@
+use crate::error::Result;
+use polars::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+
+type ScalarFn = dyn Fn(&[Series]) -> Result<Series> + Send + Sync;
+
+/// 用 Rust 闭包实现的标量函数，参数和返回值的类型在注册时声明
+#[derive(Clone)]
+pub struct ScalarFunction {
+    pub args: Vec<DataType>,
+    pub returns: DataType,
+    function: Arc<ScalarFn>,
+}
+
+impl fmt::Debug for ScalarFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScalarFunction")
+            .field("args", &self.args)
+            .field("returns", &self.returns)
+            .finish()
+    }
+}
+
+impl ScalarFunction {
+    pub fn new<F>(args: Vec<DataType>, returns: DataType, function: F) -> Self
+    where
+        F: Fn(&[Series]) -> Result<Series> + Send + Sync + 'static,
+    {
+        Self {
+            args,
+            returns,
+            function: Arc::new(function),
+        }
+    }
+
+    /// 生成调用这个函数的表达式。参数先转换成声明的类型，结果再转换成声明的返回类型。
+    /// 调用前需要保证参数个数和声明的一致，并且至少有一个参数
+    pub(crate) fn call(&self, args: Vec<Expr>) -> Expr {
+        let mut args = args
+            .into_iter()
+            .zip(&self.args)
+            .map(|(expr, dtype)| expr.cast(dtype.clone()));
+        let first = args
+            .next()
+            .expect("scalar function takes at least one argument");
+
+        let function = self.function.clone();
+        let returns = self.returns.clone();
+        let udf = move |s: &mut [Series]| {
+            let out = function(s).map_err(|e| PolarsError::ComputeError(e.to_string().into()))?;
+            if out.dtype() == &returns {
+                Ok(out)
+            } else {
+                out.cast_with_dtype(&returns)
+            }
+        };
+
+        // polars 只提供单个输入的 map，这里借用它生成的 Function 表达式，再换上多个输入
+        let mut expr = first.map(Ok, GetOutput::from_type(self.returns.clone()));
+        if let Expr::Function {
+            input, function, ..
+        } = &mut expr
+        {
+            input.extend(args);
+            *function = NoEq::new(Arc::new(udf));
+        }
+        expr
+    }
+}
+
+/// 注册过的标量函数，函数名不区分大小写
+#[derive(Debug, Clone, Default)]
+pub struct Functions(HashMap<String, ScalarFunction>);
+
+impl Functions {
+    pub fn insert(&mut self, name: &str, function: ScalarFunction) {
+        self.0.insert(name.to_lowercase(), function);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<ScalarFunction> {
+        self.0.remove(&name.to_lowercase())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ScalarFunction> {
+        self.0.get(&name.to_lowercase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::numbers_context;
+
+    #[tokio::test]
+    async fn scalar_function_works() {
+        let (mut ctx, _file) = numbers_context();
+        ctx.register_function(
+            "label",
+            vec![DataType::Utf8, DataType::Int64],
+            DataType::Utf8,
+            |args| {
+                let names = args[0].utf8()?;
+                let values = args[1].i64()?;
+                let out: Utf8Chunked = names
+                    .into_iter()
+                    .zip(values)
+                    .map(|(n, v)| Some(format!("{}={}", n?, v?)))
+                    .collect();
+                Ok(out.into_series())
+            },
+        );
+        ctx.register_function(
+            "double",
+            vec![DataType::Float64],
+            DataType::Float64,
+            |args| Ok(&args[0] * 2),
+        );
+
+        let sql = "SELECT LABEL(name, value) AS label FROM numbers \
+                   WHERE double(value) >= 36 ORDER BY value";
+        let ds = ctx.query(sql).await.unwrap();
+        let labels: Vec<_> = ds
+            .column("label")
+            .unwrap()
+            .utf8()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(labels, [Some("row18=18"), Some("row19=19")]);
+
+        let err = ctx
+            .query("SELECT double(value, 1) FROM numbers")
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("function expects 1 argument(s)"));
+
+        ctx.deregister_function("double");
+        assert!(ctx
+            .query("SELECT double(value) FROM numbers")
+            .await
+            .is_err());
+    }
+}
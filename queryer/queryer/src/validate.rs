@@ -0,0 +1,110 @@
+use crate::error::{QueryError, Result};
+use polars::prelude::DataFrame;
+use sqlparser::ast::Ident;
+
+/// 最多给出几个建议
+const MAX_SUGGESTIONS: usize = 3;
+
+/// 检查 SQL 里引用的列都在数据源里，找不到时按编辑距离给出相近的列名
+pub fn validate_columns(columns: &[Ident], df: &DataFrame) -> Result<()> {
+    let names = df.get_column_names();
+    for id in columns {
+        if names.contains(&id.value.as_str()) {
+            continue;
+        }
+
+        return Err(QueryError::UnknownColumn {
+            name: id.value.clone(),
+            span: id.span,
+            suggestions: suggest(&id.value, &names),
+        });
+    }
+    Ok(())
+}
+
+/// 找出和 name 足够接近的候选，距离小的在前
+fn suggest(name: &str, candidates: &[&str]) -> Vec<String> {
+    let name = name.to_lowercase();
+    // 允许的最大编辑距离随名字长度增加
+    let threshold = (name.chars().count() / 3).max(1);
+
+    let mut matched: Vec<(usize, &str)> = candidates
+        .iter()
+        .map(|c| (edit_distance(&name, &c.to_lowercase()), *c))
+        .filter(|(d, _)| *d <= threshold)
+        .collect();
+    matched.sort();
+
+    matched
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, c)| c.to_string())
+        .collect()
+}
+
+/// 编辑距离，相邻字符交换也算一次编辑（Optimal String Alignment）
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::*;
+    use sqlparser::tokenizer::{Location, Span};
+
+    #[test]
+    fn edit_distance_works() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("name", "name"), 0);
+        assert_eq!(edit_distance("valeu", "value"), 1);
+    }
+
+    #[test]
+    fn suggest_works() {
+        let candidates = ["name", "total_cases", "new_cases", "total_deaths"];
+        assert_eq!(suggest("nme", &candidates), vec!["name"]);
+        assert_eq!(suggest("Total_Case", &candidates), vec!["total_cases"]);
+        assert_eq!(suggest("new_case", &candidates), vec!["new_cases"]);
+        assert!(suggest("population", &candidates).is_empty());
+    }
+
+    #[test]
+    fn unknown_column_reports_position_and_suggestion() {
+        let df = DataFrame::new(vec![
+            Series::new("name", &["a"]),
+            Series::new("value", &[1]),
+        ])
+        .unwrap();
+        let span = Span::new(Location::new(3, 7), Location::new(3, 12));
+        let columns = [Ident::new("name"), Ident::with_span(span, "valeu")];
+
+        let err = validate_columns(&columns, &df).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Column `valeu` not found at Line: 3, Column: 7, did you mean `value`?"
+        );
+        assert!(validate_columns(&columns[..1], &df).is_ok());
+    }
+}
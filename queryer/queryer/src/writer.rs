@@ -0,0 +1,166 @@
+use crate::error::{QueryError, Result};
+use crate::json::lists_to_text;
+use crate::stats::timed;
+use crate::DataSet;
+use polars::prelude::*;
+use sqlparser::tokenizer::Span;
+use std::io::Cursor;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{field, info_span};
+
+/// 查询结果的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Format {
+    Csv,
+    /// 每行一个 JSON 对象
+    Json,
+    Parquet,
+    /// Arrow IPC 文件格式
+    Ipc,
+}
+
+impl FromStr for Format {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" | "ndjson" => Ok(Format::Json),
+            "parquet" => Ok(Format::Parquet),
+            "ipc" | "arrow" | "feather" => Ok(Format::Ipc),
+            v => Err(QueryError::Unsupported {
+                message: "Format is not supported".into(),
+                fragment: v.to_string(),
+                span: Span::empty(),
+            }),
+        }
+    }
+}
+
+impl Format {
+    /// 根据文件扩展名判断格式
+    pub fn from_path(path: &str) -> Option<Self> {
+        let ext = path.rsplit_once('.')?.1;
+        ext.parse().ok()
+    }
+}
+
+/// 把 DataSet 按指定格式序列化成字节
+pub fn serialize(ds: &DataSet, format: Format) -> Result<Vec<u8>> {
+    serialize_timed(ds, format).0
+}
+
+/// 序列化，同时返回用了多久
+fn serialize_timed(ds: &DataSet, format: Format) -> (Result<Vec<u8>>, Duration) {
+    let span = info_span!(
+        "serialize",
+        ?format,
+        rows = ds.height(),
+        bytes = field::Empty,
+        elapsed_ms = field::Empty
+    );
+    timed(&span, || {
+        let mut buf = Cursor::new(Vec::new());
+        match format {
+            Format::Csv => CsvWriter::new(&mut buf).finish(&lists_to_text(ds)?)?,
+            Format::Json => JsonWriter::new(&mut buf).finish(&lists_to_text(ds)?)?,
+            Format::Parquet => ParquetWriter::new(&mut buf).finish(ds)?,
+            Format::Ipc => IpcWriter::new(&mut buf).finish(ds)?,
+        }
+        let buf = buf.into_inner();
+        span.record("bytes", &(buf.len() as u64));
+        Ok(buf)
+    })
+}
+
+/// 把 DataSet 写到本地文件，target 可以是 `file://` 地址或者路径。
+/// 没有指定格式时根据扩展名判断，返回序列化用的时间
+pub async fn write_file(ds: &DataSet, target: &str, format: Option<Format>) -> Result<Duration> {
+    let path = match target.strip_prefix("file://") {
+        Some(path) => path,
+        None if target.contains("://") => {
+            return Err(QueryError::execution(format!(
+                "We only support writing to local files, got {}",
+                target
+            )))
+        }
+        None => target,
+    };
+
+    let format = format.or_else(|| Format::from_path(path)).ok_or_else(|| {
+        QueryError::execution(format!("Cannot decide output format for {}", target))
+    })?;
+
+    let (data, elapsed) = serialize_timed(ds, format);
+    tokio::fs::write(path, data?).await?;
+    Ok(elapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::numbers_context;
+
+    #[test]
+    fn format_from_path_works() {
+        assert_eq!(Format::from_path("/tmp/a.parquet"), Some(Format::Parquet));
+        assert_eq!(Format::from_path("/tmp/a.CSV"), Some(Format::Csv));
+        assert_eq!(Format::from_path("/tmp/a.json"), Some(Format::Json));
+        assert_eq!(Format::from_path("/tmp/a.arrow"), Some(Format::Ipc));
+        assert_eq!(Format::from_path("/tmp/a"), None);
+    }
+
+    #[tokio::test]
+    async fn write_file_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let df = DataFrame::new(vec![Series::new("name", &["a", "b"])]).unwrap();
+        let ds = DataSet(df);
+
+        // 没有指定格式时按扩展名判断，指定的格式优先
+        let csv = dir.path().join("out.csv").display().to_string();
+        write_file(&ds, &format!("file://{}", csv), None)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&csv).unwrap(), "name\na\nb\n");
+        let json = dir.path().join("out.txt").display().to_string();
+        write_file(&ds, &json, Some(Format::Json)).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&json).unwrap(),
+            "{\"name\":\"a\"}\n{\"name\":\"b\"}\n"
+        );
+
+        for target in [json.as_str(), "http://example.com/out.csv"] {
+            assert!(matches!(
+                write_file(&ds, target, None).await,
+                Err(QueryError::Execution(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn copy_to_file_works() {
+        let (ctx, _file) = numbers_context();
+        let dir = tempfile::tempdir().unwrap();
+
+        for (name, format) in [
+            ("out.csv", "csv"),
+            ("out.json", "json"),
+            ("out.parquet", "parquet"),
+        ] {
+            let target = dir.path().join(name);
+            let sql = format!(
+                "COPY (SELECT name FROM numbers WHERE value >= 18) TO 'file://{}' (FORMAT {})",
+                target.display(),
+                format
+            );
+            let ds = ctx.query(sql).await.unwrap();
+            assert_eq!(ds.column("rows").unwrap().get(0), AnyValue::Int64(2));
+            assert!(std::fs::metadata(&target).unwrap().len() > 0);
+        }
+
+        let csv = std::fs::read_to_string(dir.path().join("out.csv")).unwrap();
+        assert_eq!(csv, "name\nrow18\nrow19\n");
+    }
+}